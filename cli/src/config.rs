@@ -1,15 +1,72 @@
 use std::net::SocketAddr;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
 use std::{env, fs};
 
-use anyhow::{Context, Result};
+use anyhow::{bail, Context, Result};
+use dori_lib::key;
+use dori_lib::key::CipherKey;
+use dori_lib::operation::CommandLimits;
+use dori_lib::stream::{
+    DEFAULT_HEARTBEAT_INTERVAL, DEFAULT_MAX_FRAME_SIZE, DEFAULT_MAX_MISSED_HEARTBEATS,
+    DEFAULT_REKEY_AFTER_BYTES, DEFAULT_REKEY_INTERVAL,
+};
 use serde::{Deserialize, Serialize};
 
+/// The default amount of time a command may run on the client before it is killed.
+const DEFAULT_COMMAND_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// The maximum amount of bytes of each output stream of a command that is returned.
+const MAX_COMMAND_OUTPUT: u32 = 1024 * 1024;
+
 #[derive(Deserialize, Serialize)]
 pub struct HostConfig {
     bind_address: SocketAddr,
     client_name: String,
-    key: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    key: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    key_file: Option<PathBuf>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    tls_cert: Option<PathBuf>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    tls_key: Option<PathBuf>,
+    #[serde(default = "default_max_frame_size")]
+    max_frame_size: u32,
+    #[serde(default = "default_rekey_after_bytes")]
+    rekey_after_bytes: u64,
+    #[serde(default = "default_rekey_interval_secs")]
+    rekey_interval_secs: u64,
+    #[serde(default = "default_heartbeat_interval_secs")]
+    heartbeat_interval_secs: u64,
+    #[serde(default = "default_max_missed_heartbeats")]
+    max_missed_heartbeats: u32,
+    #[serde(default = "default_command_timeout_secs")]
+    command_timeout_secs: u64,
+}
+
+fn default_max_frame_size() -> u32 {
+    DEFAULT_MAX_FRAME_SIZE
+}
+
+fn default_rekey_after_bytes() -> u64 {
+    DEFAULT_REKEY_AFTER_BYTES
+}
+
+fn default_rekey_interval_secs() -> u64 {
+    DEFAULT_REKEY_INTERVAL.as_secs()
+}
+
+fn default_heartbeat_interval_secs() -> u64 {
+    DEFAULT_HEARTBEAT_INTERVAL.as_secs()
+}
+
+fn default_max_missed_heartbeats() -> u32 {
+    DEFAULT_MAX_MISSED_HEARTBEATS
+}
+
+fn default_command_timeout_secs() -> u64 {
+    DEFAULT_COMMAND_TIMEOUT.as_secs()
 }
 
 impl HostConfig {
@@ -23,9 +80,70 @@ impl HostConfig {
         &self.client_name
     }
 
+    /// Returns the paths of the TLS certificate and private key.
+    ///
+    /// Returns None if TLS is disabled, which is the case if neither `tls_cert` nor `tls_key` is
+    /// set.
+    pub fn tls(&self) -> Result<Option<(&Path, &Path)>> {
+        match (&self.tls_cert, &self.tls_key) {
+            (Some(cert), Some(key)) => Ok(Some((cert, key))),
+            (None, None) => Ok(None),
+            _ => bail!("Configuration must contain both tls_cert and tls_key or neither"),
+        }
+    }
+
+    /// Returns the maximum size of a frame received from the client in bytes.
+    pub fn max_frame_size(&self) -> u32 {
+        self.max_frame_size
+    }
+
+    /// Returns the amount of bytes sent under one key before the session is rekeyed.
+    pub fn rekey_after_bytes(&self) -> u64 {
+        self.rekey_after_bytes
+    }
+
+    /// Returns the amount of time a key is used for before the session is rekeyed.
+    pub fn rekey_interval(&self) -> Duration {
+        Duration::from_secs(self.rekey_interval_secs)
+    }
+
+    /// Returns the amount of idle time after which a heartbeat is sent to the client.
+    pub fn heartbeat_interval(&self) -> Result<Duration> {
+        match self.heartbeat_interval_secs {
+            0 => bail!("heartbeat_interval_secs must be at least 1"),
+            secs => Ok(Duration::from_secs(secs)),
+        }
+    }
+
+    /// Returns the amount of heartbeats the client may miss before it is considered dead.
+    pub fn max_missed_heartbeats(&self) -> u32 {
+        self.max_missed_heartbeats
+    }
+
+    /// Returns the limits of commands executed on the client.
+    ///
+    /// The output is limited to a quarter of the maximum frame size per stream, so that the reply
+    /// can always be read.
+    pub fn command_limits(&self) -> CommandLimits {
+        let max_output = MAX_COMMAND_OUTPUT.min(self.max_frame_size / 4);
+        CommandLimits::new(max_output, Duration::from_secs(self.command_timeout_secs))
+    }
+
     /// Returns the cipher key used for secure streams.
-    pub fn key(&self) -> &str {
-        &self.key
+    ///
+    /// Prefers the binary key material in `key_file` over the `key` passphrase, which is stretched
+    /// with a random salt. Clients learn the salt during the handshake.
+    pub fn key(&self) -> Result<CipherKey> {
+        if let Some(path) = &self.key_file {
+            return CipherKey::from_file(path)
+                .with_context(|| format!("Failed to read key file {}", path.display()));
+        }
+        match &self.key {
+            Some(passphrase) => CipherKey::Passphrase(passphrase.clone())
+                .stretch(key::generate_salt())
+                .context("Failed to stretch the passphrase"),
+            None => bail!("Configuration must contain either key or key_file"),
+        }
     }
 }
 
@@ -34,7 +152,16 @@ impl Default for HostConfig {
         Self {
             bind_address: SocketAddr::from(([127, 0, 0, 1], 12700)),
             client_name: "my-client".to_string(),
-            key: "<- Enter cipher key here ->".to_string(),
+            key: Some("<- Enter cipher key here ->".to_string()),
+            key_file: None,
+            tls_cert: None,
+            tls_key: None,
+            max_frame_size: DEFAULT_MAX_FRAME_SIZE,
+            rekey_after_bytes: DEFAULT_REKEY_AFTER_BYTES,
+            rekey_interval_secs: DEFAULT_REKEY_INTERVAL.as_secs(),
+            heartbeat_interval_secs: DEFAULT_HEARTBEAT_INTERVAL.as_secs(),
+            max_missed_heartbeats: DEFAULT_MAX_MISSED_HEARTBEATS,
+            command_timeout_secs: DEFAULT_COMMAND_TIMEOUT.as_secs(),
         }
     }
 }
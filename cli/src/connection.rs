@@ -1,64 +1,294 @@
+use std::collections::HashMap;
 use std::io;
-use std::io::Write;
-use std::net::{TcpListener, ToSocketAddrs};
+use std::io::{Read, Write};
+use std::net::{Shutdown, TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use std::thread;
 use std::time::Duration;
 
 use dori_lib::handshake;
-use dori_lib::handshake::Handshake;
-use dori_lib::operation::{Operation, Response};
-use dori_lib::stream::SecureTcpStream;
-use tora::read::ToraRead;
+use dori_lib::handshake::{Handshake, HostRejectionReason};
+use dori_lib::key::CipherKey;
+use dori_lib::operation::{Operation, Reply, Request, RequestId, Response};
+use dori_lib::stream::{
+    ChannelId, SecureStream, CONTROL_CHANNEL, DEFAULT_HEARTBEAT_INTERVAL, DEFAULT_MAX_FRAME_SIZE,
+    DEFAULT_MAX_MISSED_HEARTBEATS, DEFAULT_REKEY_AFTER_BYTES, DEFAULT_REKEY_INTERVAL,
+};
+#[cfg(feature = "tls")]
+use dori_lib::tls;
+#[cfg(feature = "tls")]
+use dori_lib::tls::{HostTransport, MaybeTlsStream, ServerConfig};
 use tora::write::ToraWrite;
 
+/// The read timeout of established connections, which bounds how long a read waits before
+/// heartbeats are sent and checked.
+const POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+/// The transport of connections accepted by [ClientListener].
+#[cfg(feature = "tls")]
+pub type Transport = HostTransport;
+
+/// The transport of connections accepted by [ClientListener].
+#[cfg(not(feature = "tls"))]
+pub type Transport = TcpStream;
+
 /// A secure connection to the client.
-pub struct ClientConnection {
-    stream: SecureTcpStream,
+///
+/// Runs over TCP by default, but any [Read] + [Write] transport can be used. The stream is shared
+/// with the keepalive thread, if any.
+///
+/// Every operation is sent with a new request ID, so several operations can be in flight at once.
+/// Replies that arrive before they are asked for are kept until they are. Operations can be sent
+/// on separate channels, so that a busy channel does not hold up the others.
+pub struct ClientConnection<T = TcpStream> {
+    stream: Arc<Mutex<SecureStream<T>>>,
+    socket: Option<TcpStream>,
+    next_id: RequestId,
+    replies: HashMap<RequestId, Response>,
 }
 
-impl ClientConnection {
+impl<T> ClientConnection<T>
+where
+    T: Read + Write,
+{
+    /// Performs a handshake with the client over the given transport.
+    ///
+    /// Only accepts clients with the given name.
+    pub fn establish(
+        transport: T,
+        client_name: &str,
+        key: &CipherKey,
+    ) -> io::Result<Result<Self, HostRejectionReason>> {
+        let handshake = Handshake::new(client_name.to_string(), key.clone());
+
+        let result = handshake::perform_host_handshake(transport, handshake)?;
+
+        Ok(result.map(|stream| Self {
+            stream: Arc::new(Mutex::new(stream)),
+            socket: None,
+            next_id: 0,
+            replies: HashMap::new(),
+        }))
+    }
+
+    /// Sets the maximum size of a frame received from the client.
+    pub fn set_max_frame_size(&mut self, max_frame_size: u32) {
+        self.lock().set_max_frame_size(max_frame_size);
+    }
+
+    /// Sets the amount of bytes and time after which the session is rekeyed.
+    pub fn set_rekey_limits(&mut self, after_bytes: u64, interval: Duration) {
+        let mut stream = self.lock();
+
+        stream.set_rekey_after_bytes(after_bytes);
+        stream.set_rekey_interval(interval);
+    }
+
+    /// Sets the heartbeat interval and the amount of missed heartbeats after which the client is
+    /// considered dead.
+    pub fn set_heartbeat(&mut self, interval: Duration, max_missed: u32) {
+        let mut stream = self.lock();
+
+        stream.set_heartbeat_interval(interval);
+        stream.set_max_missed_heartbeats(max_missed);
+    }
+
     /// Serializes and writes the given operation to the inner stream.
     /// Flushes the inner stream.
-    pub fn send_operation(&mut self, operation: &Operation) -> io::Result<()> {
-        self.stream.writes(operation)?;
-        self.stream.flush()
+    ///
+    /// Returns the ID of the request, which is used to read its response.
+    pub fn send_operation(&mut self, operation: Operation) -> io::Result<RequestId> {
+        self.send_operation_on(CONTROL_CHANNEL, operation)
+    }
+
+    /// Serializes and writes the given operation to the given channel of the inner stream.
+    /// Flushes the inner stream.
+    ///
+    /// The client replies on the same channel. Returns the ID of the request, which is used to
+    /// read its response.
+    pub fn send_operation_on(
+        &mut self,
+        channel: ChannelId,
+        operation: Operation,
+    ) -> io::Result<RequestId> {
+        let id = self.next_id;
+        let request = Request::new(id, operation);
+
+        {
+            let mut stream = self.lock();
+            stream.writes(&request)?;
+            stream.flush_to(channel)?;
+        }
+        self.next_id += 1;
+        Ok(id)
+    }
+
+    /// Reads and deserializes the [Response] to the request with the given ID.
+    ///
+    /// Replies to other requests that are read in the meantime are kept for later.
+    pub fn read_response(&mut self, id: RequestId) -> io::Result<Response> {
+        if let Some(response) = self.replies.remove(&id) {
+            return Ok(response);
+        }
+
+        loop {
+            let (_, reply): (_, Reply) = self.lock().reads_any()?;
+
+            if reply.id() == id {
+                return Ok(reply.into_response());
+            }
+            if reply.id() >= self.next_id || self.replies.contains_key(&reply.id()) {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "Client replied to an unknown request",
+                ));
+            }
+            self.replies.insert(reply.id(), reply.into_response());
+        }
+    }
+
+    /// Returns the response to the request with the given ID if it was already read by
+    /// [read_response](Self::read_response).
+    pub fn take_response(&mut self, id: RequestId) -> Option<Response> {
+        self.replies.remove(&id)
+    }
+
+    /// Reads and deserializes the next [Reply] from the stream, whichever request it belongs to.
+    ///
+    /// Returns None if no reply arrived before the transport read timed out. Replies kept by
+    /// [read_response](Self::read_response) are not returned.
+    pub fn poll_reply(&mut self) -> io::Result<Option<Reply>> {
+        Ok(self.lock().try_reads_any()?.map(|(_, reply)| reply))
     }
 
-    /// Reads and deserializes [Response] from the stream.
-    pub fn read_response(&mut self) -> io::Result<Response> {
-        self.stream.reads()
+    /// Sets the TCP socket the transport runs over.
+    ///
+    /// Its read timeout is set to [POLL_INTERVAL], which bounds how long a read waits before
+    /// heartbeats are sent and checked.
+    pub fn attach_socket(&mut self, socket: TcpStream) -> io::Result<()> {
+        socket.set_read_timeout(Some(POLL_INTERVAL))?;
+        self.socket = Some(socket);
+        Ok(())
+    }
+
+    /// Sets the read timeout of the attached socket, which bounds how long
+    /// [poll_reply](Self::poll_reply) blocks.
+    ///
+    /// Pass None to restore the default of [POLL_INTERVAL].
+    pub fn set_poll_interval(&self, interval: Option<Duration>) -> io::Result<()> {
+        match &self.socket {
+            Some(socket) => socket.set_read_timeout(Some(interval.unwrap_or(POLL_INTERVAL))),
+            None => Ok(()),
+        }
+    }
+
+    /// Locks the inner stream.
+    fn lock(&self) -> MutexGuard<'_, SecureStream<T>> {
+        self.stream.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl<T> ClientConnection<T>
+where
+    T: Read + Write + Send + 'static,
+{
+    /// Keeps the connection alive from a background thread while the host is idle.
+    ///
+    /// The thread sends heartbeats and checks on the client twice per heartbeat interval, starting
+    /// right away, so that the client learns the host's interval before it judges the host by its
+    /// own. Once the client is considered dead, the loss is reported and the attached socket, if
+    /// any, is shut down. The thread stops when the connection is dropped.
+    pub fn spawn_keepalive(&self) -> io::Result<()> {
+        let Some(interval) = self.lock().heartbeat_interval() else {
+            return Ok(());
+        };
+        let socket = self.socket.as_ref().map(TcpStream::try_clone).transpose()?;
+        let stream = Arc::downgrade(&self.stream);
+
+        thread::spawn(move || loop {
+            let Some(stream) = stream.upgrade() else {
+                break;
+            };
+            let result = stream
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .keepalive();
+
+            if let Err(err) = result {
+                println!("\nConnection to client lost: {err}");
+
+                if let Some(socket) = &socket {
+                    let _ = socket.shutdown(Shutdown::Both);
+                }
+                break;
+            }
+            drop(stream);
+            thread::sleep(interval / 2);
+        });
+        Ok(())
     }
 }
 
 /// A listener that only establishes secure connections with the specified client.
 pub struct ClientListener {
     inner: TcpListener,
+    max_frame_size: u32,
+    rekey_after_bytes: u64,
+    rekey_interval: Duration,
+    heartbeat_interval: Duration,
+    max_missed_heartbeats: u32,
+    #[cfg(feature = "tls")]
+    tls: Option<Arc<ServerConfig>>,
 }
 
 impl ClientListener {
     /// Listens for incoming connections and attempts to establish a secure connection.
     ///
-    /// Only accepts clients with the given name.
-    pub fn accept_from(&self, client_name: &str, key: &str) -> io::Result<ClientConnection> {
-        let stream = loop {
+    /// Only accepts clients with the given name. Connections that fail the handshake are reported
+    /// and dropped. The established connection is kept alive by a background thread.
+    pub fn accept_from(
+        &self,
+        client_name: &str,
+        key: &CipherKey,
+    ) -> io::Result<ClientConnection<Transport>> {
+        let (mut connection, socket) = loop {
             let (conn, end_addr) = self.inner.accept()?;
             let timeout = Duration::from_secs(30);
 
             conn.set_read_timeout(Some(timeout))?;
             conn.set_write_timeout(Some(timeout))?;
 
+            let socket = conn.try_clone()?;
+
             println!("Initiating handshake with {end_addr}..");
 
-            let handshake = Handshake::new(client_name.to_string(), key.to_string());
+            let transport = match self.secure(conn) {
+                Ok(transport) => transport,
+                Err(err) => {
+                    println!("TLS handshake failed: {err}");
+                    continue;
+                }
+            };
 
-            match handshake::perform_host_handshake(conn, handshake)? {
-                Ok(stream) => break stream,
-                Err(reason) => {
+            match ClientConnection::establish(transport, client_name, key) {
+                Ok(Ok(connection)) => break (connection, socket),
+                Ok(Err(reason)) => {
                     println!("Handshake failed: {reason:?}");
                     continue;
                 }
+                Err(err) => {
+                    println!("Handshake failed: {err}");
+                    continue;
+                }
             }
         };
-        Ok(ClientConnection { stream })
+        connection.set_max_frame_size(self.max_frame_size);
+        connection.set_rekey_limits(self.rekey_after_bytes, self.rekey_interval);
+        connection.set_heartbeat(self.heartbeat_interval, self.max_missed_heartbeats);
+
+        connection.attach_socket(socket)?;
+        connection.spawn_keepalive()?;
+        Ok(connection)
     }
 
     /// Binds a listener to the given address.
@@ -68,6 +298,52 @@ impl ClientListener {
     {
         Ok(Self {
             inner: TcpListener::bind(addr)?,
+            max_frame_size: DEFAULT_MAX_FRAME_SIZE,
+            rekey_after_bytes: DEFAULT_REKEY_AFTER_BYTES,
+            rekey_interval: DEFAULT_REKEY_INTERVAL,
+            heartbeat_interval: DEFAULT_HEARTBEAT_INTERVAL,
+            max_missed_heartbeats: DEFAULT_MAX_MISSED_HEARTBEATS,
+            #[cfg(feature = "tls")]
+            tls: None,
         })
     }
+
+    /// Sets the maximum size of a frame received from accepted clients.
+    pub fn set_max_frame_size(&mut self, max_frame_size: u32) {
+        self.max_frame_size = max_frame_size;
+    }
+
+    /// Sets the amount of bytes and time after which sessions with accepted clients are rekeyed.
+    pub fn set_rekey_limits(&mut self, after_bytes: u64, interval: Duration) {
+        self.rekey_after_bytes = after_bytes;
+        self.rekey_interval = interval;
+    }
+
+    /// Sets the heartbeat interval of accepted clients and the amount of missed heartbeats after
+    /// which they are considered dead.
+    pub fn set_heartbeat(&mut self, interval: Duration, max_missed: u32) {
+        self.heartbeat_interval = interval;
+        self.max_missed_heartbeats = max_missed;
+    }
+
+    /// Wraps accepted connections in TLS with the given configuration.
+    #[cfg(feature = "tls")]
+    pub fn set_tls_config(&mut self, config: Arc<ServerConfig>) {
+        self.tls = Some(config);
+    }
+
+    /// Performs the TLS handshake with the accepted connection, if TLS is enabled.
+    #[cfg(feature = "tls")]
+    fn secure(&self, conn: TcpStream) -> io::Result<Transport> {
+        match &self.tls {
+            Some(config) => tls::accept(config.clone(), conn),
+            None => Ok(MaybeTlsStream::Plain(conn)),
+        }
+    }
+
+    /// Returns the accepted connection as is, since TLS support is not compiled in.
+    #[cfg(not(feature = "tls"))]
+    fn secure(&self, conn: TcpStream) -> io::Result<Transport> {
+        Ok(conn)
+    }
 }
@@ -0,0 +1,194 @@
+use std::time::SystemTime;
+
+use anyhow::{bail, Result};
+use dori_lib::operation::{FileStat, FileType, Operation, Response};
+
+use crate::confirm;
+use crate::connection::{ClientConnection, Transport};
+use crate::transfer::format_size;
+
+/// Lists the entries of the client-side directory.
+pub fn list(stream: &mut ClientConnection<Transport>, path: &str) -> Result<()> {
+    let id = stream.send_operation(Operation::ListDirectory(path.to_string()))?;
+
+    let Response::ListDirectory(res) = stream.read_response(id)? else {
+        bail!("Invalid response")
+    };
+
+    let entries = match res {
+        Ok(entries) => entries,
+        Err(err) => {
+            println!("Client error: {err}");
+            return Ok(());
+        }
+    };
+
+    for entry in entries.iter() {
+        let stat = entry.stat();
+        let size = match stat.file_type() {
+            FileType::File => format_size(stat.size()),
+            _ => "-".to_string(),
+        };
+        let name = match (stat.file_type(), stat.link_target()) {
+            (FileType::Directory, _) => format!("{}/", entry.name()),
+            (FileType::Symlink, Some(target)) => format!("{} -> {target}", entry.name()),
+            _ => entry.name().to_string(),
+        };
+
+        println!(
+            "{:<10}  {size:>10}  {:<20}  {name}",
+            format_permissions(stat),
+            format_time(stat.modified())
+        );
+    }
+    Ok(())
+}
+
+/// Prints the status of the client-side entry.
+pub fn stat(stream: &mut ClientConnection<Transport>, path: &str) -> Result<()> {
+    let id = stream.send_operation(Operation::Stat(path.to_string()))?;
+
+    let Response::Stat(res) = stream.read_response(id)? else {
+        bail!("Invalid response")
+    };
+
+    let stat = match res {
+        Ok(stat) => stat,
+        Err(err) => {
+            println!("Client error: {err}");
+            return Ok(());
+        }
+    };
+    let file_type = match stat.file_type() {
+        FileType::File => "file",
+        FileType::Directory => "directory",
+        FileType::Symlink => "symbolic link",
+        FileType::Other => "other",
+    };
+
+    println!("Path:      {path}");
+    println!("Type:      {file_type}");
+    println!(
+        "Size:      {} ({} bytes)",
+        format_size(stat.size()),
+        stat.size()
+    );
+    println!("Modified:  {}", format_time(stat.modified()));
+    println!("Accessed:  {}", format_time(stat.accessed()));
+    println!("Created:   {}", format_time(stat.created()));
+
+    match stat.mode() {
+        Some(mode) => println!(
+            "Mode:      {:04o} ({})",
+            mode & 0o7777,
+            format_permissions(&stat)
+        ),
+        None => println!("Read-only: {}", if stat.readonly() { "yes" } else { "no" }),
+    }
+    if let Some(target) = stat.link_target() {
+        println!("Target:    {target}");
+    }
+    Ok(())
+}
+
+/// Creates the client-side directory and all of its missing parents.
+pub fn make_dir(stream: &mut ClientConnection<Transport>, path: &str) -> Result<()> {
+    let id = stream.send_operation(Operation::CreateDirectory(path.to_string()))?;
+
+    let Response::CreateDirectory(res) = stream.read_response(id)? else {
+        bail!("Invalid response")
+    };
+
+    if let Err(err) = res {
+        println!("Client error: {err}");
+    }
+    Ok(())
+}
+
+/// Removes the client-side file or directory.
+///
+/// Asks for confirmation before a directory is removed along with its contents.
+pub fn remove(stream: &mut ClientConnection<Transport>, path: &str, recursive: bool) -> Result<()> {
+    if recursive && !confirm(&format!("Remove {path} and all of its contents?")) {
+        return Ok(());
+    }
+    let id = stream.send_operation(Operation::Remove(path.to_string(), recursive))?;
+
+    let Response::Remove(res) = stream.read_response(id)? else {
+        bail!("Invalid response")
+    };
+
+    if let Err(err) = res {
+        println!("Client error: {err}");
+    }
+    Ok(())
+}
+
+/// Moves the client-side entry to another client-side path.
+pub fn rename(stream: &mut ClientConnection<Transport>, src: &str, dest: &str) -> Result<()> {
+    let id = stream.send_operation(Operation::Rename(src.to_string(), dest.to_string()))?;
+
+    let Response::Rename(res) = stream.read_response(id)? else {
+        bail!("Invalid response")
+    };
+
+    if let Err(err) = res {
+        println!("Client error: {err}");
+    }
+    Ok(())
+}
+
+/// Copies the client-side file or directory to another client-side path.
+pub fn copy(
+    stream: &mut ClientConnection<Transport>,
+    src: &str,
+    dest: &str,
+    recursive: bool,
+) -> Result<()> {
+    let op = Operation::Copy(src.to_string(), dest.to_string(), recursive);
+    let id = stream.send_operation(op)?;
+
+    let Response::Copy(res) = stream.read_response(id)? else {
+        bail!("Invalid response")
+    };
+
+    match res {
+        Ok(copied) => println!("Copied {} to {dest}", format_size(copied)),
+        Err(err) => println!("Client error: {err}"),
+    }
+    Ok(())
+}
+
+/// Formats the type and mode of the entry like `ls -l` does.
+///
+/// Only the type is shown if the client does not run on Unix.
+fn format_permissions(stat: &FileStat) -> String {
+    let kind = match stat.file_type() {
+        FileType::File => '-',
+        FileType::Directory => 'd',
+        FileType::Symlink => 'l',
+        FileType::Other => '?',
+    };
+    let Some(mode) = stat.mode() else {
+        return kind.to_string();
+    };
+
+    let mut formatted = String::from(kind);
+
+    for shift in [6, 3, 0] {
+        let bits = mode >> shift;
+
+        formatted.push(if bits & 4 != 0 { 'r' } else { '-' });
+        formatted.push(if bits & 2 != 0 { 'w' } else { '-' });
+        formatted.push(if bits & 1 != 0 { 'x' } else { '-' });
+    }
+    formatted
+}
+
+/// Formats the time in UTC, or a dash if it is unknown.
+fn format_time(time: Option<SystemTime>) -> String {
+    match time {
+        Some(time) => humantime::format_rfc3339_seconds(time).to_string(),
+        None => "-".to_string(),
+    }
+}
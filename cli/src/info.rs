@@ -0,0 +1,67 @@
+use std::time::Duration;
+
+use anyhow::{bail, Result};
+use dori_lib::operation::{Operation, Response};
+
+use crate::connection::{ClientConnection, Transport};
+use crate::transfer::format_size;
+
+/// Prints an inventory of the client's system.
+pub fn print_info(stream: &mut ClientConnection<Transport>) -> Result<()> {
+    let id = stream.send_operation(Operation::SystemInfo)?;
+
+    let Response::SystemInfo(info) = stream.read_response(id)? else {
+        bail!("Invalid response")
+    };
+    let os = info.os();
+    let unknown = || "unknown".to_string();
+
+    let mut system = os.name().map_or_else(unknown, str::to_string);
+
+    if let Some(version) = os.version() {
+        system.push(' ');
+        system.push_str(version);
+    }
+    if let Some(kernel) = os.kernel_version() {
+        system.push_str(&format!(" (kernel {kernel})"));
+    }
+
+    println!(
+        "Hostname: {}",
+        os.hostname().map_or_else(unknown, str::to_string)
+    );
+    println!("System:   {system}");
+    println!("Arch:     {}", os.arch());
+    println!("CPUs:     {}", info.cpu_count());
+    println!(
+        "Memory:   {} free of {}",
+        format_size(info.free_memory()),
+        format_size(info.total_memory())
+    );
+    println!("Uptime:   {}", format_uptime(info.uptime()));
+    println!("Client:   dori-client {}", info.client_version());
+    println!("Disks:");
+
+    for disk in info.disks() {
+        println!(
+            "  {:<20}  {:<8}  {:>10} free of {:>10}  ({})",
+            disk.mount_point(),
+            disk.file_system(),
+            format_size(disk.available_space()),
+            format_size(disk.total_space()),
+            disk.name()
+        );
+    }
+    Ok(())
+}
+
+/// Formats the uptime in days, hours and minutes.
+fn format_uptime(uptime: Duration) -> String {
+    let minutes = uptime.as_secs() / 60;
+    let (days, hours, minutes) = (minutes / (24 * 60), minutes / 60 % 24, minutes % 60);
+
+    match days {
+        0 => format!("{hours}h {minutes}m"),
+        _ => format!("{days}d {hours}h {minutes}m"),
+    }
+}
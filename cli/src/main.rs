@@ -1,18 +1,34 @@
-use std::fs;
-use std::path::PathBuf;
-use std::path::Path;
+use std::io::Write;
+#[cfg(unix)]
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::{Path, PathBuf};
 use std::time::Instant;
+use std::{fs, io};
+
 use anyhow::{bail, Context, Result};
 use clap::{Parser, Subcommand};
 use cnsl::readln;
-use dori_lib::operation::{FileTransferOperation, Operation, Response};
+use dori_lib::key::CipherKey;
+use dori_lib::operation::{
+    CommandLimits, CommandOutput, HashAlgorithm, Operation, ProcessTarget, Response, Signal,
+};
+#[cfg(feature = "tls")]
+use dori_lib::tls;
 use rand::Rng;
-use crate::config::{HostConfig, load_config};
 
+use crate::config::{load_config, HostConfig};
 use crate::connection::ClientListener;
+use crate::tree::Filter;
 
 mod config;
 mod connection;
+mod filesystem;
+mod info;
+mod processes;
+mod shell;
+mod transfer;
+mod tree;
+mod verify;
 
 // TODO add operation implementation
 
@@ -37,31 +53,111 @@ enum Command {
 
     /// Generates a 256-bit cipher key.
     GenerateKey,
+
+    /// Generates a key file with 256 bits of random key material.
+    GenerateKeyFile { path: PathBuf },
+
+    /// Generates a self-signed TLS certificate and private key for clients to pin.
+    #[cfg(feature = "tls")]
+    GenerateCert { cert: PathBuf, key: PathBuf },
+}
+
+fn confirm(prompt: &str) -> bool {
+    loop {
+        let confirm = readln!("{} Y/n", prompt);
+
+        if confirm == "y" || confirm == "Y" {
+            return true;
+        }
+        if confirm == "n" || confirm == "N" {
+            return false;
+        }
+    }
 }
 
 fn validate_file_dest(path: &Path) -> bool {
     if path.extension().is_none() {
         println!("Warning: The destination you entered does not have an extension.");
+        return confirm("Continue?");
+    }
+    true
+}
 
-        loop {
-            let confirm = readln!("Continue? Y/n");
+fn read_filter() -> Option<Filter> {
+    let include = readln!("Include globs (empty for all): ");
+    let exclude = readln!("Exclude globs: ");
 
-            if confirm == "y" || confirm == "Y" {
-                return true;
+    match Filter::parse(&include, &exclude) {
+        Ok(filter) => Some(filter),
+        Err(err) => {
+            println!("Invalid glob: {err}");
+            None
+        }
+    }
+}
+
+/// Splits the line into whitespace-separated arguments.
+///
+/// Arguments containing whitespace can be wrapped in single or double quotes. Backslashes are kept
+/// as is, so that Windows paths need no escaping. Returns None if a quote is not closed.
+fn split_args(line: &str) -> Option<Vec<String>> {
+    let mut args = Vec::new();
+    let mut chars = line.chars().peekable();
+
+    loop {
+        while chars.next_if(|c| c.is_whitespace()).is_some() {}
+
+        if chars.peek().is_none() {
+            return Some(args);
+        }
+        let mut arg = String::new();
+
+        while let Some(c) = chars.next_if(|c| !c.is_whitespace()) {
+            if c != '"' && c != '\'' {
+                arg.push(c);
+                continue;
             }
-            if confirm == "n" || confirm == "N" {
-                return false;
+            loop {
+                match chars.next()? {
+                    quote if quote == c => break,
+                    other => arg.push(other),
+                }
             }
         }
+        args.push(arg);
     }
-    true
 }
 
-fn run(config: &HostConfig) -> Result<()> {
+fn print_command_output(output: &CommandOutput, limits: CommandLimits) {
+    print!("{}", String::from_utf8_lossy(output.stdout()));
+    eprint!("{}", String::from_utf8_lossy(output.stderr()));
+
+    if output.truncated() {
+        let limit = transfer::format_size(limits.max_output().into());
+        println!("Output was truncated to {limit} per stream");
+    }
+    match (output.exit_code(), output.timed_out()) {
+        (_, true) => println!("Killed after the timeout of {:?}", limits.timeout()),
+        (Some(code), false) => println!("Exited with code {code} after {:?}", output.duration()),
+        (None, false) => println!("Terminated by a signal after {:?}", output.duration()),
+    }
+}
+
+fn run(config: &HostConfig, key: &CipherKey) -> Result<()> {
     println!("Starting listener..");
 
-    let listener = ClientListener::bind(config.bind_address()).with_context(|| "Failed to bind listener")?;
-    let mut stream = listener.accept_from(config.client_name(), config.key())?;
+    let mut listener =
+        ClientListener::bind(config.bind_address()).with_context(|| "Failed to bind listener")?;
+    listener.set_max_frame_size(config.max_frame_size());
+    listener.set_rekey_limits(config.rekey_after_bytes(), config.rekey_interval());
+    listener.set_heartbeat(config.heartbeat_interval()?, config.max_missed_heartbeats());
+
+    if let Some((cert, key)) = config.tls()? {
+        enable_tls(&mut listener, cert, key)?;
+    }
+
+    let mut stream = listener.accept_from(config.client_name(), key)?;
+    let limits = config.command_limits();
 
     loop {
         let operation = readln!(">> ");
@@ -69,8 +165,14 @@ fn run(config: &HostConfig) -> Result<()> {
         if operation.is_empty() || operation.chars().all(char::is_whitespace) {
             continue;
         }
-        
-        match operation.as_str() {
+
+        let Some(words) = split_args(&operation) else {
+            println!("Unterminated quote");
+            continue;
+        };
+        let mut args = words.iter().map(String::as_str);
+
+        match args.next().unwrap_or_default() {
             "upload" => {
                 let fname = readln!("Local path to file: ");
                 let dest = readln!("Client-side path: ");
@@ -78,34 +180,166 @@ fn run(config: &HostConfig) -> Result<()> {
                 if !validate_file_dest(&PathBuf::from(&dest)) {
                     continue;
                 }
-                
-                let data = match fs::read(&fname) {
-                    Ok(v) => v,
-                    Err(err) => {
-                        println!("Failed to read from {fname}: {err}");
+                transfer::upload(&mut stream, &fname, &dest)?;
+            }
+            "download" => {
+                let src = readln!("Client-side path to file: ");
+                let dest = readln!("Local path: ");
+
+                if !validate_file_dest(&PathBuf::from(&dest)) {
+                    continue;
+                }
+                transfer::download(&mut stream, &src, &dest)?;
+            }
+            "upload-dir" => {
+                let src = readln!("Local directory: ");
+                let dest = readln!("Client-side directory: ");
+
+                let Some(filter) = read_filter() else {
+                    continue;
+                };
+                tree::upload_dir(&mut stream, &src, &dest, &filter)?;
+            }
+            "download-dir" => {
+                let src = readln!("Client-side directory: ");
+                let dest = readln!("Local directory: ");
+
+                let Some(filter) = read_filter() else {
+                    continue;
+                };
+                tree::download_dir(&mut stream, &src, &dest, &filter)?;
+            }
+            "ls" => {
+                let (path, None) = (args.next(), args.next()) else {
+                    println!("Usage: ls [path]");
+                    continue;
+                };
+                filesystem::list(&mut stream, path.unwrap_or("."))?;
+            }
+            "stat" => {
+                let (Some(path), None) = (args.next(), args.next()) else {
+                    println!("Usage: stat <path>");
+                    continue;
+                };
+                filesystem::stat(&mut stream, path)?;
+            }
+            "mkdir" => {
+                let (Some(path), None) = (args.next(), args.next()) else {
+                    println!("Usage: mkdir <path>");
+                    continue;
+                };
+                filesystem::make_dir(&mut stream, path)?;
+            }
+            "rm" => {
+                let mut args = args.peekable();
+                let recursive = args.next_if_eq(&"-r").is_some();
+
+                let (Some(path), None) = (args.next(), args.next()) else {
+                    println!("Usage: rm [-r] <path>");
+                    continue;
+                };
+                filesystem::remove(&mut stream, path, recursive)?;
+            }
+            "mv" => {
+                let (Some(src), Some(dest), None) = (args.next(), args.next(), args.next()) else {
+                    println!("Usage: mv <source> <destination>");
+                    continue;
+                };
+                filesystem::rename(&mut stream, src, dest)?;
+            }
+            "cp" => {
+                let mut args = args.peekable();
+                let recursive = args.next_if_eq(&"-r").is_some();
+
+                let (Some(src), Some(dest), None) = (args.next(), args.next(), args.next()) else {
+                    println!("Usage: cp [-r] <source> <destination>");
+                    continue;
+                };
+                filesystem::copy(&mut stream, src, dest, recursive)?;
+            }
+            "verify" => {
+                let mut args = args.peekable();
+                let algorithm = match args.next_if_eq(&"--blake3") {
+                    Some(_) => HashAlgorithm::Blake3,
+                    None => HashAlgorithm::Sha256,
+                };
+
+                let (Some(local), Some(remote), None) = (args.next(), args.next(), args.next())
+                else {
+                    println!("Usage: verify [--blake3] <local path> <client path>");
+                    continue;
+                };
+                verify::verify(&mut stream, local, remote, algorithm)?;
+            }
+            "shell" => shell::attach(&mut stream, args.next().map(str::to_string))?,
+            "info" => info::print_info(&mut stream)?,
+            "ps" => processes::list(&mut stream, args.next())?,
+            "kill" => {
+                let mut args = args.peekable();
+                let signal = args
+                    .next_if(|arg| arg.starts_with('-'))
+                    .map(|arg| &arg[1..]);
+
+                let (Some(target), None) = (args.next(), args.next()) else {
+                    println!("Usage: kill [-signal] <pid | name>");
+                    continue;
+                };
+                let signal = match signal.map(processes::parse_signal) {
+                    None => None,
+                    Some(Some(signal)) => Some(signal),
+                    Some(None) => {
+                        let names: Vec<_> = Signal::ALL.iter().map(Signal::name).collect();
+                        println!("Unknown signal, expected one of {}", names.join(", "));
                         continue;
                     }
                 };
-                
-                let op = FileTransferOperation::new(dest, data);
-                stream.send_operation(&Operation::Upload(op))?;
+                let target = match target.parse() {
+                    Ok(pid) => ProcessTarget::Pid(pid),
+                    Err(_) => ProcessTarget::Name(target.to_string()),
+                };
+                processes::kill(&mut stream, target, signal)?;
+            }
+            "exec" => {
+                let Some(program) = args.next() else {
+                    println!("Usage: exec <program> [arguments..]");
+                    continue;
+                };
+                let args: Vec<_> = args.map(str::to_string).collect();
+                let op = Operation::Command(program.to_string(), args.into(), limits);
 
-                let Response::Upload(res) = stream.read_response()? else {
+                let id = stream.send_operation(op)?;
+
+                let Response::Command(res) = stream.read_response(id)? else {
                     bail!("Invalid response")
                 };
 
-                if let Err(err) = res {
-                    println!("Client error: {err}");
+                match res {
+                    Ok(output) => print_command_output(&output, limits),
+                    Err(err) => println!("Client error: {err}"),
                 }
             }
             "ping" => {
+                let count = match args.next().map(str::parse::<usize>) {
+                    None => 1,
+                    Some(Ok(count)) if count > 0 => count,
+                    Some(_) => {
+                        println!("Usage: ping [count]");
+                        continue;
+                    }
+                };
                 let now = Instant::now();
-                stream.send_operation(&Operation::Ping)?;
 
-                let Ok(Response::Pong) = stream.read_response() else {
-                    bail!("Invalid response")
-                };
-                println!("Ping: {:?}", now.elapsed());
+                // All pings are sent before any pong is read, so that they are pipelined.
+                let ids = (0..count)
+                    .map(|_| stream.send_operation(Operation::Ping))
+                    .collect::<io::Result<Vec<_>>>()?;
+
+                for id in ids {
+                    let Ok(Response::Pong) = stream.read_response(id) else {
+                        bail!("Invalid response")
+                    };
+                    println!("Ping: {:?}", now.elapsed());
+                }
             }
             _ => {
                 println!("Unrecognized operation");
@@ -114,6 +348,22 @@ fn run(config: &HostConfig) -> Result<()> {
     }
 }
 
+#[cfg(feature = "tls")]
+fn enable_tls(listener: &mut ClientListener, cert: &Path, key: &Path) -> Result<()> {
+    let cert = tls::load_certificate(cert)
+        .with_context(|| format!("Failed to read certificate {}", cert.display()))?;
+    let key = tls::load_private_key(key)
+        .with_context(|| format!("Failed to read private key {}", key.display()))?;
+
+    listener.set_tls_config(tls::server_config(cert, key)?);
+    Ok(())
+}
+
+#[cfg(not(feature = "tls"))]
+fn enable_tls(_listener: &mut ClientListener, _cert: &Path, _key: &Path) -> Result<()> {
+    bail!("TLS requires dori-cli to be built with the tls feature")
+}
+
 fn generate_key() {
     let mut rng = rand::thread_rng();
     let mut bytes = Vec::with_capacity(64);
@@ -127,6 +377,64 @@ fn generate_key() {
     println!("{key}");
 }
 
+/// Writes the contents to a new file opened with the given options.
+///
+/// Fails if the file already exists, so that an existing file is never replaced.
+fn write_new(path: &Path, options: &mut fs::OpenOptions, contents: &[u8]) -> Result<()> {
+    let mut file = match options.write(true).create_new(true).open(path) {
+        Err(err) if err.kind() == io::ErrorKind::AlreadyExists => {
+            bail!(
+                "{} already exists, refusing to overwrite it",
+                path.display()
+            )
+        }
+        res => res.with_context(|| format!("Failed to write to {}", path.display()))?,
+    };
+    file.write_all(contents)
+        .with_context(|| format!("Failed to write to {}", path.display()))
+}
+
+/// Writes the secret to a new file that only the current user can read on Unix.
+///
+/// Fails if the file already exists, so that an existing secret is never replaced.
+fn write_secret(path: &Path, contents: &[u8]) -> Result<()> {
+    let mut options = fs::OpenOptions::new();
+
+    #[cfg(unix)]
+    options.mode(0o600);
+
+    write_new(path, &mut options, contents)
+}
+
+fn generate_key_file(path: &Path) -> Result<()> {
+    let bytes: [u8; 32] = rand::random();
+
+    write_secret(path, &bytes)?;
+    println!("Generated key file {}", path.display());
+    Ok(())
+}
+
+#[cfg(feature = "tls")]
+fn generate_cert(cert_path: &Path, key_path: &Path) -> Result<()> {
+    let certified = rcgen::generate_simple_self_signed(vec![tls::SERVER_NAME.to_string()])
+        .with_context(|| "Failed to generate certificate")?;
+
+    // The private key is written first, so that no certificate is left without its key. An
+    // existing certificate may be pinned by clients, so it is never replaced either.
+    write_secret(key_path, certified.signing_key.serialize_pem().as_bytes())?;
+
+    let cert = certified.cert.pem();
+
+    if let Err(err) = write_new(cert_path, &mut fs::OpenOptions::new(), cert.as_bytes()) {
+        let _ = fs::remove_file(key_path);
+        return Err(err);
+    }
+
+    println!("Generated certificate {}", cert_path.display());
+    println!("Generated private key {}", key_path.display());
+    Ok(())
+}
+
 fn main() -> Result<()> {
     let cli = Cli::parse();
 
@@ -136,18 +444,22 @@ fn main() -> Result<()> {
 
         Command::Run { name } => {
             let config = load_config(&name)?;
+            let key = config.key()?;
 
             loop {
-                if let Err(err) = run(&config) {
+                if let Err(err) = run(&config, &key) {
                     println!("Error: {err}");
                     continue;
                 }
                 break Ok(());
             }
-        },
+        }
         Command::GenerateKey => {
             generate_key();
             Ok(())
         }
+        Command::GenerateKeyFile { path } => generate_key_file(&path),
+        #[cfg(feature = "tls")]
+        Command::GenerateCert { cert, key } => generate_cert(&cert, &key),
     }
 }
@@ -0,0 +1,91 @@
+use anyhow::{bail, Result};
+use dori_lib::operation::{Operation, ProcessTarget, Response, Signal};
+
+use crate::connection::{ClientConnection, Transport};
+use crate::transfer::format_size;
+
+/// Lists the processes running on the client.
+///
+/// Only processes whose name or command line contains the filter are listed, ignoring case.
+pub fn list(stream: &mut ClientConnection<Transport>, filter: Option<&str>) -> Result<()> {
+    let id = stream.send_operation(Operation::ListProcesses)?;
+
+    let Response::ListProcesses(processes) = stream.read_response(id)? else {
+        bail!("Invalid response")
+    };
+    let filter = filter.map(str::to_lowercase);
+
+    println!(
+        "{:>7}  {:>7}  {:>6}  {:>10}  {:<16}  COMMAND",
+        "PID", "PPID", "CPU%", "MEMORY", "NAME"
+    );
+
+    for process in processes.iter() {
+        let command = match process.command().is_empty() {
+            true => format!("[{}]", process.name()),
+            false => process.command().join(" "),
+        };
+        if let Some(filter) = &filter {
+            let name = process.name().to_lowercase();
+
+            if !name.contains(filter) && !command.to_lowercase().contains(filter) {
+                continue;
+            }
+        }
+        let parent = process
+            .parent_pid()
+            .map_or("-".to_string(), |pid| pid.to_string());
+
+        println!(
+            "{:>7}  {parent:>7}  {:>6.1}  {:>10}  {:<16}  {command}",
+            process.pid(),
+            process.cpu_usage(),
+            format_size(process.memory()),
+            process.name()
+        );
+    }
+    Ok(())
+}
+
+/// Terminates the client-side processes the target matches with the given signal.
+pub fn kill(
+    stream: &mut ClientConnection<Transport>,
+    target: ProcessTarget,
+    signal: Option<Signal>,
+) -> Result<()> {
+    let id = stream.send_operation(Operation::Kill(target, signal))?;
+
+    let Response::Kill(res) = stream.read_response(id)? else {
+        bail!("Invalid response")
+    };
+
+    match res {
+        Ok(pids) => {
+            let pids: Vec<_> = pids.iter().map(u32::to_string).collect();
+
+            match signal {
+                Some(signal) => println!("Sent {signal} to {}", pids.join(", ")),
+                None => println!("Terminated {}", pids.join(", ")),
+            }
+        }
+        Err(err) => println!("Client error: {err}"),
+    }
+    Ok(())
+}
+
+/// Parses a signal given by its name, with or without the SIG prefix, or by its number.
+///
+/// Only the numbers that are the same on all Unix platforms are accepted.
+pub fn parse_signal(signal: &str) -> Option<Signal> {
+    let signal = signal.to_uppercase();
+    let name = signal.strip_prefix("SIG").unwrap_or(&signal);
+
+    match name {
+        "1" => Some(Signal::Hangup),
+        "2" => Some(Signal::Interrupt),
+        "3" => Some(Signal::Quit),
+        "9" => Some(Signal::Kill),
+        "15" => Some(Signal::Terminate),
+        _ => Signal::ALL.into_iter().find(|signal| signal.name() == name),
+    }
+}
@@ -0,0 +1,191 @@
+use std::io;
+use std::io::Write;
+use std::time::Duration;
+
+use anyhow::{bail, Result};
+use crossterm::event::{Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use crossterm::{event, terminal};
+use dori_lib::operation::{Operation, RequestId, Response, ShellOptions};
+
+use crate::connection::{ClientConnection, Transport};
+
+/// The read timeout of the connection while attached to a shell, which bounds how long input is
+/// held back.
+const SHELL_POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// Opens an interactive shell session on the client and attaches the terminal to it.
+///
+/// Runs the client's default shell unless a program is given. Returns once the shell exits, or
+/// terminates it once Ctrl+] is pressed.
+pub fn attach(stream: &mut ClientConnection<Transport>, program: Option<String>) -> Result<()> {
+    let (cols, rows) = match terminal::size() {
+        Ok(size) => size,
+        Err(err) => {
+            println!("Failed to get terminal size: {err}");
+            return Ok(());
+        }
+    };
+    let id = stream.send_operation(Operation::OpenShell(ShellOptions::new(program, cols, rows)))?;
+
+    let Response::OpenShell(res) = stream.read_response(id)? else {
+        bail!("Invalid response")
+    };
+
+    if let Err(err) = res {
+        println!("Client error: {err}");
+        return Ok(());
+    }
+
+    // The shell is already running, so it is terminated rather than left behind.
+    if let Err(err) = terminal::enable_raw_mode() {
+        println!("Failed to enable raw mode: {err}");
+        return close(stream, id);
+    }
+    print!("Attached to shell, press Ctrl+] to terminate it\r\n");
+
+    let result = stream
+        .set_poll_interval(Some(SHELL_POLL_INTERVAL))
+        .map_err(Into::into)
+        .and_then(|_| forward(stream, id));
+
+    let _ = terminal::disable_raw_mode();
+    stream.set_poll_interval(None)?;
+
+    match result? {
+        Some(code) => println!("\nShell exited with code {code}"),
+        None => println!("\nShell was terminated"),
+    }
+    Ok(())
+}
+
+/// Terminates the shell of the session and discards its output until it exits.
+fn close(stream: &mut ClientConnection<Transport>, id: RequestId) -> Result<()> {
+    stream.send_operation(Operation::CloseShell(id))?;
+
+    loop {
+        let Some(reply) = stream.poll_reply()? else {
+            continue;
+        };
+        if reply.id() != id {
+            bail!("Invalid response");
+        }
+
+        match reply.into_response() {
+            Response::ShellOutput(_) => {}
+            Response::ShellExit(_) => return Ok(()),
+            _ => bail!("Invalid response"),
+        }
+    }
+}
+
+/// Forwards terminal input to the session and its output to the terminal until the shell exits.
+///
+/// Returns the exit code of the shell.
+fn forward(stream: &mut ClientConnection<Transport>, id: RequestId) -> Result<Option<i32>> {
+    let mut stdout = io::stdout();
+    let mut closing = false;
+
+    loop {
+        while event::poll(Duration::ZERO)? {
+            let op = match event::read()? {
+                Event::Key(key) if key.kind == KeyEventKind::Release => continue,
+                Event::Key(key) if is_detach(&key) => {
+                    if closing {
+                        continue;
+                    }
+                    closing = true;
+                    Operation::CloseShell(id)
+                }
+                Event::Key(key) => match key_input(&key) {
+                    Some(data) => Operation::ShellInput(id, data.into()),
+                    None => continue,
+                },
+                Event::Resize(cols, rows) => Operation::ResizeShell(id, cols, rows),
+                _ => continue,
+            };
+            stream.send_operation(op)?;
+        }
+
+        let Some(reply) = stream.poll_reply()? else {
+            continue;
+        };
+        if reply.id() != id {
+            bail!("Invalid response");
+        }
+
+        match reply.into_response() {
+            Response::ShellOutput(data) => {
+                stdout.write_all(&data)?;
+                stdout.flush()?;
+            }
+            Response::ShellExit(code) => return Ok(code),
+            _ => bail!("Invalid response"),
+        }
+    }
+}
+
+/// Returns true if the key is Ctrl+], which terminates the shell.
+///
+/// Some terminals report Ctrl+] as Ctrl+5, since both send the same control character.
+fn is_detach(key: &KeyEvent) -> bool {
+    key.modifiers.contains(KeyModifiers::CONTROL) && matches!(key.code, KeyCode::Char(']' | '5'))
+}
+
+/// Encodes the key as the bytes a terminal would send for it.
+fn key_input(key: &KeyEvent) -> Option<Vec<u8>> {
+    let seq: &[u8] = match key.code {
+        KeyCode::Char(c) if key.modifiers.contains(KeyModifiers::CONTROL) => {
+            let byte = match c.to_ascii_lowercase() {
+                c @ 'a'..='z' => c as u8 - b'a' + 1,
+                ' ' | '@' | '2' => 0,
+                '[' | '3' => 0x1b,
+                '\\' | '4' => 0x1c,
+                '^' | '6' => 0x1e,
+                '_' | '7' => 0x1f,
+                _ => return None,
+            };
+            return Some(alt_prefixed(key, vec![byte]));
+        }
+        KeyCode::Char(c) => return Some(alt_prefixed(key, c.to_string().into_bytes())),
+        KeyCode::Enter => b"\r",
+        KeyCode::Tab => b"\t",
+        KeyCode::BackTab => b"\x1b[Z",
+        KeyCode::Backspace => b"\x7f",
+        KeyCode::Esc => b"\x1b",
+        KeyCode::Up => b"\x1b[A",
+        KeyCode::Down => b"\x1b[B",
+        KeyCode::Right => b"\x1b[C",
+        KeyCode::Left => b"\x1b[D",
+        KeyCode::Home => b"\x1b[H",
+        KeyCode::End => b"\x1b[F",
+        KeyCode::Insert => b"\x1b[2~",
+        KeyCode::Delete => b"\x1b[3~",
+        KeyCode::PageUp => b"\x1b[5~",
+        KeyCode::PageDown => b"\x1b[6~",
+        KeyCode::F(n) => match n {
+            1 => b"\x1bOP",
+            2 => b"\x1bOQ",
+            3 => b"\x1bOR",
+            4 => b"\x1bOS",
+            5 => b"\x1b[15~",
+            6 => b"\x1b[17~",
+            7 => b"\x1b[18~",
+            8 => b"\x1b[19~",
+            9 => b"\x1b[20~",
+            10 => b"\x1b[21~",
+            11 => b"\x1b[23~",
+            12 => b"\x1b[24~",
+            _ => return None,
+        },
+        _ => return None,
+    };
+    Some(seq.to_vec())
+}
+
+/// Prefixes the input with an escape if Alt is held, like terminals do.
+fn alt_prefixed(key: &KeyEvent, mut input: Vec<u8>) -> Vec<u8> {
+    if key.modifiers.contains(KeyModifiers::ALT) {
+        input.insert(0, 0x1b);
+    }
+    input
+}
@@ -0,0 +1,479 @@
+use std::cell::Cell;
+use std::collections::VecDeque;
+use std::fmt::{Display, Formatter};
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+use std::{fmt, fs, io};
+
+use anyhow::{bail, Result};
+use dori_lib::operation::{
+    ClientError, FileChunk, FileMetadata, IoErrorKind, Operation, RequestId, Response,
+};
+use dori_lib::transfer;
+use dori_lib::transfer::{FileHasher, CHUNK_LEN, TRANSFER_CHANNEL};
+
+use crate::confirm;
+use crate::connection::{ClientConnection, Transport};
+
+/// The maximum amount of chunks sent before the client confirmed the first of them.
+const CHUNKS_IN_FLIGHT: usize = 4;
+
+/// The outcome of a single file transfer, carrying the size of the file if it succeeded.
+pub type Outcome = std::result::Result<u64, TransferError>;
+
+/// A reason a single file could not be transferred, which does not end the session.
+pub enum TransferError {
+    /// The client failed to access the file.
+    Client(ClientError),
+
+    /// The local file could not be accessed.
+    Local(PathBuf, io::Error),
+
+    /// The downloaded file does not match the digest of the client-side file.
+    Mismatch,
+
+    /// The path of a tree entry is not contained in the tree, like one starting with `..`.
+    InvalidPath,
+}
+
+impl Display for TransferError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Client(err) => write!(f, "Client error: {err}"),
+            Self::Local(path, err) => write!(f, "{}: {err}", path.display()),
+            Self::Mismatch => write!(f, "File digest does not match, the download was discarded"),
+            Self::InvalidPath => write!(f, "Path is not contained in the directory tree"),
+        }
+    }
+}
+
+/// Prints the progress of a transfer on a single line, which is ended once this is dropped.
+struct Progress {
+    action: &'static str,
+    size: u64,
+    width: Cell<usize>,
+}
+
+impl Progress {
+    /// Starts printing the progress of a transfer of the given size.
+    fn new(action: &'static str, done: u64, size: u64) -> Self {
+        let progress = Self {
+            action,
+            size,
+            width: Cell::new(0),
+        };
+
+        progress.update(done, None);
+        progress
+    }
+
+    /// Prints the progress and the latest latency of the connection over the previous progress.
+    fn update(&self, done: u64, latency: Option<Duration>) {
+        let percent = match self.size {
+            0 => 100,
+            _ => done * 100 / self.size,
+        };
+        let mut line = format!(
+            "{}: {percent}% ({} of {})",
+            self.action,
+            format_size(done),
+            format_size(self.size)
+        );
+
+        if let Some(latency) = latency {
+            line.push_str(&format!(", ping {} ms", latency.as_millis()));
+        }
+        print!("\r{line:<width$}", width = self.width.get());
+        let _ = io::stdout().flush();
+
+        self.width.set(line.len());
+    }
+}
+
+impl Drop for Progress {
+    fn drop(&mut self) {
+        println!();
+    }
+}
+
+/// Measures the latency of the connection on the control channel while a transfer keeps the
+/// transfer channel busy.
+///
+/// At most one ping is in flight. Its pong is picked up whenever the transfer reads its replies.
+struct HealthCheck {
+    ping: Option<(RequestId, Instant)>,
+    latency: Option<Duration>,
+}
+
+impl HealthCheck {
+    /// Instantiates a new HealthCheck without a ping in flight.
+    const fn new() -> Self {
+        Self {
+            ping: None,
+            latency: None,
+        }
+    }
+
+    /// Sends a ping unless one is still in flight.
+    fn ping(&mut self, stream: &mut ClientConnection<Transport>) -> Result<()> {
+        if self.ping.is_none() {
+            self.ping = Some((stream.send_operation(Operation::Ping)?, Instant::now()));
+        }
+        Ok(())
+    }
+
+    /// Returns the latest latency, taking the pong of the ping in flight if it arrived.
+    fn latency(&mut self, stream: &mut ClientConnection<Transport>) -> Result<Option<Duration>> {
+        if let Some((id, sent)) = self.ping {
+            match stream.take_response(id) {
+                Some(Response::Pong) => {
+                    self.latency = Some(sent.elapsed());
+                    self.ping = None;
+                }
+                Some(_) => bail!("Invalid response"),
+                None => {}
+            }
+        }
+        Ok(self.latency)
+    }
+
+    /// Reads the pong of the ping in flight, if any, so that it is not left behind.
+    fn finish(self, stream: &mut ClientConnection<Transport>) -> Result<()> {
+        if let Some((id, _)) = self.ping {
+            stream.read_response(id)?;
+        }
+        Ok(())
+    }
+}
+
+/// Uploads the local file to the given client-side path.
+///
+/// Offers to create the destination's parent directories if they do not exist on the client.
+pub fn upload(stream: &mut ClientConnection<Transport>, fname: &str, dest: &str) -> Result<()> {
+    match send_file(stream, Path::new(fname), dest)? {
+        Ok(size) => println!("Uploaded {} to {dest}", format_size(size)),
+        Err(err) => {
+            println!("{err}");
+
+            if let TransferError::Client(err) = &err {
+                if offer_create_parent(stream, dest, err)? {
+                    return upload(stream, fname, dest);
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Downloads the client-side file to the given local path.
+pub fn download(stream: &mut ClientConnection<Transport>, src: &str, dest: &str) -> Result<()> {
+    match receive_file(stream, src, Path::new(dest))? {
+        Ok(size) => println!("Downloaded {} to {dest}", format_size(size)),
+        Err(err) => println!("{err}"),
+    }
+    Ok(())
+}
+
+/// Sends the local file to the given client-side path.
+///
+/// The file is streamed in chunks and verified by its SHA-256 digest once it is complete. An
+/// interrupted upload of the file to the same path resumes where the client stopped receiving.
+/// The file keeps its Unix mode if both sides run on Unix.
+pub fn send_file(
+    stream: &mut ClientConnection<Transport>,
+    src: &Path,
+    dest: &str,
+) -> Result<Outcome> {
+    let local = |err| TransferError::Local(src.to_path_buf(), err);
+
+    let opened = File::open(src).and_then(|file| Ok((file.metadata()?, file)));
+
+    let (metadata, mut file) = match opened {
+        Ok(opened) => opened,
+        Err(err) => return Ok(Err(local(err))),
+    };
+    let size = metadata.len();
+
+    let id = stream.send_operation(Operation::BeginUpload(dest.to_string(), size))?;
+
+    let Response::BeginUpload(res) = stream.read_response(id)? else {
+        bail!("Invalid response")
+    };
+
+    let offset = match res {
+        Ok(offset) => offset,
+        Err(err) => return Ok(Err(TransferError::Client(err))),
+    };
+    let mut hasher = FileHasher::new();
+
+    // The digest covers the whole file, including the part received before an interruption.
+    if offset > 0 {
+        println!("Resuming upload at {}", format_size(offset));
+
+        if let Err(err) = io::copy(&mut (&mut file).take(offset), &mut hasher) {
+            return Ok(Err(local(err)));
+        }
+    }
+
+    let mut in_flight = VecDeque::new();
+    let mut sent = offset;
+    let mut health = HealthCheck::new();
+    let progress = Progress::new("Uploading", offset, size);
+
+    loop {
+        health.ping(stream)?;
+
+        if in_flight.len() < CHUNKS_IN_FLIGHT && sent < size {
+            let mut data = Vec::with_capacity(CHUNK_LEN);
+            let read = (&mut file)
+                .take((size - sent).min(CHUNK_LEN as u64))
+                .read_to_end(&mut data);
+
+            let err = match read {
+                Ok(0) => io::Error::new(io::ErrorKind::UnexpectedEof, "File was truncated"),
+                Ok(len) => {
+                    hasher.update(&data);
+
+                    let op = Operation::UploadChunk(FileChunk::new(dest.to_string(), sent, data));
+                    in_flight.push_back(stream.send_operation_on(TRANSFER_CHANNEL, op)?);
+                    sent += len as u64;
+                    continue;
+                }
+                Err(err) => err,
+            };
+            health.finish(stream)?;
+            discard_replies(stream, in_flight)?;
+            return Ok(Err(local(err)));
+        }
+
+        let Some(id) = in_flight.pop_front() else {
+            break;
+        };
+        let Response::UploadChunk(res) = stream.read_response(id)? else {
+            bail!("Invalid response")
+        };
+
+        match res {
+            Ok(received) => progress.update(received, health.latency(stream)?),
+            Err(err) => {
+                health.finish(stream)?;
+                discard_replies(stream, in_flight)?;
+                return Ok(Err(TransferError::Client(err)));
+            }
+        }
+    }
+    health.finish(stream)?;
+    drop(progress);
+
+    let id = stream.send_operation(Operation::FinishUpload(
+        dest.to_string(),
+        hasher.finish().into(),
+        transfer::file_mode(&metadata),
+    ))?;
+
+    let Response::FinishUpload(res) = stream.read_response(id)? else {
+        bail!("Invalid response")
+    };
+    Ok(res.map(|()| size).map_err(TransferError::Client))
+}
+
+/// Receives the client-side file at the given local path.
+///
+/// The file is streamed in chunks and verified by its SHA-256 digest once it is complete. An
+/// interrupted download of the file to the same path resumes where it stopped. The downloaded file
+/// keeps the modification time and read-only flag it has on the client, and its Unix mode if both
+/// sides run on Unix.
+pub fn receive_file(
+    stream: &mut ClientConnection<Transport>,
+    src: &str,
+    dest: &Path,
+) -> Result<Outcome> {
+    let id = stream.send_operation(Operation::Download(src.to_string()))?;
+
+    let Response::Download(res) = stream.read_response(id)? else {
+        bail!("Invalid response")
+    };
+
+    let metadata = match res {
+        Ok(metadata) => metadata,
+        Err(err) => return Ok(Err(TransferError::Client(err))),
+    };
+    let size = metadata.size();
+    let partial = transfer::partial_path(dest);
+    let local = |err| TransferError::Local(partial.clone(), err);
+
+    let (mut file, offset) = match open_partial(&partial, size) {
+        Ok(opened) => opened,
+        Err(err) => return Ok(Err(local(err))),
+    };
+    let mut hasher = FileHasher::new();
+
+    // Hashing the received data also moves the cursor to its end, where the download continues.
+    if offset > 0 {
+        println!("Resuming download at {}", format_size(offset));
+
+        if let Err(err) = io::copy(&mut (&mut file).take(offset), &mut hasher) {
+            return Ok(Err(local(err)));
+        }
+    }
+
+    let mut in_flight = VecDeque::new();
+    let mut requested = offset;
+    let mut received = offset;
+    let mut health = HealthCheck::new();
+    let progress = Progress::new("Downloading", offset, size);
+
+    loop {
+        health.ping(stream)?;
+
+        if in_flight.len() < CHUNKS_IN_FLIGHT && requested < size {
+            let op = Operation::DownloadChunk(src.to_string(), requested);
+
+            in_flight.push_back(stream.send_operation_on(TRANSFER_CHANNEL, op)?);
+            requested += CHUNK_LEN as u64;
+            continue;
+        }
+
+        let Some(id) = in_flight.pop_front() else {
+            break;
+        };
+        let Response::DownloadChunk(res) = stream.read_response(id)? else {
+            bail!("Invalid response")
+        };
+
+        let err = match res.map(|data| file.write_all(&data).map(|()| data)) {
+            Ok(Ok(data)) => {
+                hasher.update(&data);
+                received += data.len() as u64;
+                progress.update(received, health.latency(stream)?);
+                continue;
+            }
+            Ok(Err(err)) => local(err),
+            Err(err) => TransferError::Client(err),
+        };
+        health.finish(stream)?;
+        discard_replies(stream, in_flight)?;
+        return Ok(Err(err));
+    }
+    health.finish(stream)?;
+    drop(progress);
+    drop(file);
+
+    // A file that changed on the client during the download does not match its digest either.
+    if hasher.finish() != metadata.digest() {
+        let _ = fs::remove_file(&partial);
+        return Ok(Err(TransferError::Mismatch));
+    }
+
+    match fs::rename(&partial, dest).and_then(|()| apply_metadata(dest, &metadata)) {
+        Ok(()) => Ok(Ok(size)),
+        Err(err) => Ok(Err(TransferError::Local(dest.to_path_buf(), err))),
+    }
+}
+
+/// Opens the partially downloaded file of the given size.
+///
+/// Returns the file and the amount of bytes that were already received. Data received beyond the
+/// size of the file must belong to another version of it and is discarded.
+fn open_partial(path: &Path, size: u64) -> io::Result<(File, u64)> {
+    let file = OpenOptions::new()
+        .create(true)
+        .truncate(false)
+        .read(true)
+        .write(true)
+        .open(path)?;
+
+    let received = file.metadata()?.len();
+
+    if received <= size {
+        return Ok((file, received));
+    }
+    file.set_len(0)?;
+    Ok((file, 0))
+}
+
+/// Applies the modification time and permissions of the client-side file to the local file.
+fn apply_metadata(path: &Path, metadata: &FileMetadata) -> io::Result<()> {
+    if let Some(modified) = metadata.modified() {
+        File::options()
+            .write(true)
+            .open(path)?
+            .set_modified(modified)?;
+    }
+
+    match metadata.mode() {
+        Some(mode) if cfg!(unix) => transfer::set_file_mode(path, mode),
+        _ if metadata.readonly() => {
+            let mut permissions = fs::metadata(path)?.permissions();
+
+            permissions.set_readonly(true);
+            fs::set_permissions(path, permissions)
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Reads the replies to the remaining requests of an aborted transfer, so that none is left
+/// behind.
+fn discard_replies<I>(stream: &mut ClientConnection<Transport>, ids: I) -> Result<()>
+where
+    I: IntoIterator<Item = RequestId>,
+{
+    for id in ids {
+        stream.read_response(id)?;
+    }
+    Ok(())
+}
+
+/// Offers to create the parent directories of the client-side path if the error says they do
+/// not exist.
+///
+/// Returns true if the directories were created.
+fn offer_create_parent(
+    stream: &mut ClientConnection<Transport>,
+    dest: &str,
+    err: &ClientError,
+) -> Result<bool> {
+    let parent = Path::new(dest)
+        .parent()
+        .filter(|p| !p.as_os_str().is_empty());
+
+    let (Some(IoErrorKind::NotFound), Some(parent)) = (err.io_kind(), parent) else {
+        return Ok(false);
+    };
+    if !confirm(&format!("Create {} on the client?", parent.display())) {
+        return Ok(false);
+    }
+    let id = stream.send_operation(Operation::CreateDirectory(parent.display().to_string()))?;
+
+    let Response::CreateDirectory(res) = stream.read_response(id)? else {
+        bail!("Invalid response")
+    };
+
+    match res {
+        Ok(()) => Ok(true),
+        Err(err) => {
+            println!("Client error: {err}");
+            Ok(false)
+        }
+    }
+}
+
+/// Formats the amount of bytes with a binary unit.
+pub fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["KiB", "MiB", "GiB", "TiB"];
+
+    if bytes < 1024 {
+        return format!("{bytes} B");
+    }
+    let mut size = bytes as f64 / 1024.0;
+    let mut unit = 0;
+
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    format!("{size:.1} {}", UNITS[unit])
+}
@@ -0,0 +1,306 @@
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+
+use anyhow::{bail, Result};
+use dori_lib::operation::{Operation, Response, TreeEntry};
+use dori_lib::transfer;
+use globset::{Glob, GlobSet, GlobSetBuilder};
+
+use crate::connection::{ClientConnection, Transport};
+use crate::transfer::{format_size, receive_file, send_file, Outcome, TransferError};
+
+/// Include and exclude globs that select the entries of a directory tree.
+///
+/// Globs are matched against paths relative to the root of the tree, with `*` also matching `/`.
+/// Excluding a directory excludes all of its contents.
+pub struct Filter {
+    include: Option<GlobSet>,
+    exclude: GlobSet,
+}
+
+impl Filter {
+    /// Parses the whitespace-separated include and exclude globs.
+    ///
+    /// All files are included if no include globs are given.
+    pub fn parse(include: &str, exclude: &str) -> Result<Self, globset::Error> {
+        let include = match include.trim().is_empty() {
+            true => None,
+            false => Some(build_set(include)?),
+        };
+
+        Ok(Self {
+            include,
+            exclude: build_set(exclude)?,
+        })
+    }
+
+    /// Returns the entries of the tree that pass the filter, in their original order.
+    ///
+    /// Once include globs are given, directories are only kept if they contain an included file
+    /// or are empty, so that the structure around excluded files is not recreated.
+    fn select(&self, entries: Vec<TreeEntry>) -> Vec<TreeEntry> {
+        let Some(include) = &self.include else {
+            return entries
+                .into_iter()
+                .filter(|entry| !self.is_excluded(entry.path()))
+                .collect();
+        };
+        let mut non_empty = HashSet::new();
+        let mut needed = HashSet::new();
+
+        for entry in &entries {
+            let selected = !entry.is_dir()
+                && include.is_match(entry.path())
+                && !self.is_excluded(entry.path());
+
+            for ancestor in ancestors(entry.path()) {
+                non_empty.insert(ancestor.to_string());
+
+                if selected {
+                    needed.insert(ancestor.to_string());
+                }
+            }
+        }
+
+        entries
+            .into_iter()
+            .filter(|entry| !self.is_excluded(entry.path()))
+            .filter(|entry| match entry.is_dir() {
+                true => needed.contains(entry.path()) || !non_empty.contains(entry.path()),
+                false => include.is_match(entry.path()),
+            })
+            .collect()
+    }
+
+    /// Returns true if the path or one of its ancestors matches an exclude glob.
+    fn is_excluded(&self, path: &str) -> bool {
+        self.exclude.is_match(path) || ancestors(path).any(|a| self.exclude.is_match(a))
+    }
+}
+
+/// The outcomes of the entries of a directory transfer.
+#[derive(Default)]
+struct Summary {
+    files: Vec<(String, Outcome)>,
+    directories: usize,
+    failed_directories: Vec<(String, TransferError)>,
+}
+
+impl Summary {
+    /// Records the outcome of a file transfer.
+    fn file(&mut self, path: &str, outcome: Outcome) {
+        self.files.push((path.to_string(), outcome));
+    }
+
+    /// Records the outcome of creating a directory.
+    fn directory(&mut self, path: &str, res: Result<(), TransferError>) {
+        match res {
+            Ok(()) => self.directories += 1,
+            Err(err) => self.failed_directories.push((path.to_string(), err)),
+        }
+    }
+
+    /// Records an entry that was skipped because its path is not contained in the tree.
+    fn invalid(&mut self, entry: &TreeEntry) {
+        match entry.is_dir() {
+            true => self.directory(entry.path(), Err(TransferError::InvalidPath)),
+            false => self.file(entry.path(), Err(TransferError::InvalidPath)),
+        }
+    }
+
+    /// Prints the outcome of every file and failed directory, followed by the totals.
+    fn print(&self, action: &str) {
+        let mut transferred = 0;
+        let mut bytes = 0;
+
+        println!("\nSummary:");
+
+        for (path, outcome) in &self.files {
+            match outcome {
+                Ok(size) => {
+                    transferred += 1;
+                    bytes += size;
+                    println!("  {:>10}  {path}", format_size(*size));
+                }
+                Err(err) => println!("  {:>10}  {path}: {err}", "failed"),
+            }
+        }
+        for (path, err) in &self.failed_directories {
+            println!("  {:>10}  {path}/: {err}", "failed");
+        }
+
+        let failed = self.files.len() - transferred + self.failed_directories.len();
+
+        println!(
+            "{action} {transferred} files ({}) and {} directories, {failed} failed",
+            format_size(bytes),
+            self.directories
+        );
+    }
+}
+
+/// Uploads the local directory tree to the given client-side directory.
+///
+/// Relative paths and empty directories are preserved, and so are the Unix modes of files if both
+/// sides run on Unix. Directories are created with the client's default mode. A file that fails
+/// to upload does not stop the others.
+pub fn upload_dir(
+    stream: &mut ClientConnection<Transport>,
+    src: &str,
+    dest: &str,
+    filter: &Filter,
+) -> Result<()> {
+    if dest.is_empty() {
+        println!("The client-side directory must not be empty");
+        return Ok(());
+    }
+    let entries = match transfer::walk_tree(src) {
+        Ok(entries) => filter.select(entries),
+        Err(err) => {
+            println!("Failed to read from {src}: {err}");
+            return Ok(());
+        }
+    };
+
+    if let Err(err) = create_remote_dir(stream, dest)? {
+        println!("{err}");
+        return Ok(());
+    }
+    let mut summary = Summary::default();
+
+    for entry in entries {
+        let (Some(local), Some(remote)) = (
+            local_path(src, entry.path()),
+            remote_path(dest, entry.path()),
+        ) else {
+            summary.invalid(&entry);
+            continue;
+        };
+
+        if entry.is_dir() {
+            summary.directory(entry.path(), create_remote_dir(stream, &remote)?);
+            continue;
+        }
+        println!("{}", entry.path());
+
+        let outcome = send_file(stream, &local, &remote)?;
+        summary.file(entry.path(), outcome);
+    }
+    summary.print("Uploaded");
+    Ok(())
+}
+
+/// Downloads the client-side directory tree to the given local directory.
+///
+/// Relative paths and empty directories are preserved, and so are the Unix modes of files if both
+/// sides run on Unix. Directories are created with the default mode. A file that fails to
+/// download does not stop the others, and neither does an entry whose path is not contained in
+/// the tree, which is skipped.
+pub fn download_dir(
+    stream: &mut ClientConnection<Transport>,
+    src: &str,
+    dest: &str,
+    filter: &Filter,
+) -> Result<()> {
+    let id = stream.send_operation(Operation::ListTree(src.to_string()))?;
+
+    let Response::ListTree(res) = stream.read_response(id)? else {
+        bail!("Invalid response")
+    };
+
+    let entries = match res {
+        Ok(entries) => filter.select(entries.into_inner()),
+        Err(err) => {
+            println!("Client error: {err}");
+            return Ok(());
+        }
+    };
+
+    if let Err(err) = fs::create_dir_all(dest) {
+        println!("Failed to write to {dest}: {err}");
+        return Ok(());
+    }
+    let mut summary = Summary::default();
+
+    for entry in entries {
+        let (Some(local), Some(remote)) = (
+            local_path(dest, entry.path()),
+            remote_path(src, entry.path()),
+        ) else {
+            summary.invalid(&entry);
+            continue;
+        };
+
+        if entry.is_dir() {
+            let res = fs::create_dir_all(&local).map_err(|err| TransferError::Local(local, err));
+            summary.directory(entry.path(), res);
+            continue;
+        }
+        println!("{}", entry.path());
+
+        let outcome = receive_file(stream, &remote, &local)?;
+        summary.file(entry.path(), outcome);
+    }
+    summary.print("Downloaded");
+    Ok(())
+}
+
+/// Creates the client-side directory and all of its missing parents.
+fn create_remote_dir(
+    stream: &mut ClientConnection<Transport>,
+    path: &str,
+) -> Result<Result<(), TransferError>> {
+    let id = stream.send_operation(Operation::CreateDirectory(path.to_string()))?;
+
+    let Response::CreateDirectory(res) = stream.read_response(id)? else {
+        bail!("Invalid response")
+    };
+    Ok(res.map_err(TransferError::Client))
+}
+
+/// Builds a set of the whitespace-separated globs.
+fn build_set(globs: &str) -> Result<GlobSet, globset::Error> {
+    let mut builder = GlobSetBuilder::new();
+
+    for glob in globs.split_whitespace() {
+        builder.add(Glob::new(glob)?);
+    }
+    builder.build()
+}
+
+/// Returns the ancestors of the relative path, excluding the path itself.
+fn ancestors(path: &str) -> impl Iterator<Item = &str> {
+    path.match_indices('/').map(|(i, _)| &path[..i])
+}
+
+/// Joins the client-side root and a relative path of the tree.
+///
+/// Both Unix and Windows accept `/` as a separator, so the path is joined with it either way.
+/// Returns None if the root is empty or the path is not [contained](is_contained) in the tree.
+pub fn remote_path(root: &str, path: &str) -> Option<String> {
+    (!root.is_empty() && is_contained(path))
+        .then(|| format!("{}/{path}", root.trim_end_matches(['/', '\\'])))
+}
+
+/// Joins the local root and a relative path of the tree.
+///
+/// Returns None if the path is not [contained](is_contained) in the tree.
+pub fn local_path(root: &str, path: &str) -> Option<PathBuf> {
+    is_contained(path).then(|| Path::new(root).join(path.split('/').collect::<PathBuf>()))
+}
+
+/// Returns true if the relative path of a tree entry only consists of normal components.
+///
+/// Trees listed by the client are not trusted, so this rejects paths that would leave the root,
+/// like ones with `..`, a root or a prefix. Backslashes are rejected too, since Windows treats
+/// them as separators.
+fn is_contained(path: &str) -> bool {
+    path.split('/').all(|part| {
+        let mut components = Path::new(part).components();
+
+        !part.contains('\\')
+            && matches!(components.next(), Some(Component::Normal(_)))
+            && components.next().is_none()
+    })
+}
@@ -0,0 +1,117 @@
+use std::path::PathBuf;
+use std::{fs, io};
+
+use anyhow::{bail, Result};
+use dori_lib::operation::{HashAlgorithm, Operation, Response};
+use dori_lib::transfer;
+
+use crate::connection::{ClientConnection, Transport};
+use crate::tree::{local_path, remote_path};
+
+/// A file that is compared with its counterpart on the client.
+struct Pair {
+    name: String,
+    local: PathBuf,
+    remote: String,
+}
+
+/// Compares the local file or directory tree with its counterpart on the client by their digests.
+///
+/// All files of a directory tree are hashed on the client with a single request.
+pub fn verify(
+    stream: &mut ClientConnection<Transport>,
+    local: &str,
+    remote: &str,
+    algorithm: HashAlgorithm,
+) -> Result<()> {
+    let pairs = match pairs(local, remote) {
+        Ok(pairs) => pairs,
+        Err(err) => {
+            println!("Failed to read from {local}: {err}");
+            return Ok(());
+        }
+    };
+
+    if pairs.is_empty() {
+        println!("No files to verify");
+        return Ok(());
+    }
+    let paths: Vec<_> = pairs.iter().map(|pair| pair.remote.clone()).collect();
+    let id = stream.send_operation(Operation::Hash(paths.into(), algorithm))?;
+
+    let Response::Hash(digests) = stream.read_response(id)? else {
+        bail!("Invalid response")
+    };
+    if digests.len() != pairs.len() {
+        bail!("Invalid response")
+    }
+    let mut matching = 0;
+
+    for (pair, remote) in pairs.iter().zip(digests.iter()) {
+        let remote = match remote {
+            Ok(digest) => digest,
+            Err(err) => {
+                println!("  {:<8}  {}: Client error: {err}", "error", pair.name);
+                continue;
+            }
+        };
+        let local = match transfer::hash_file(&pair.local, algorithm) {
+            Ok(digest) => digest,
+            Err(err) => {
+                println!("  {:<8}  {}: {err}", "error", pair.name);
+                continue;
+            }
+        };
+
+        if local == **remote {
+            matching += 1;
+            println!("  {:<8}  {}", "ok", pair.name);
+        } else {
+            println!("  {:<8}  {}", "mismatch", pair.name);
+            println!("  {:<8}    local:  {}", "", to_hex(&local));
+            println!("  {:<8}    client: {}", "", to_hex(remote));
+        }
+    }
+    println!("{matching} of {} files match ({algorithm})", pairs.len());
+    Ok(())
+}
+
+/// Returns the local file, or all files of the local directory tree, paired with the paths they
+/// have on the client.
+fn pairs(local: &str, remote: &str) -> io::Result<Vec<Pair>> {
+    if !fs::metadata(local)?.is_dir() {
+        return Ok(vec![Pair {
+            name: local.to_string(),
+            local: PathBuf::from(local),
+            remote: remote.to_string(),
+        }]);
+    }
+
+    transfer::walk_tree(local)?
+        .into_iter()
+        .filter(|entry| !entry.is_dir())
+        .map(|entry| {
+            let (Some(local), Some(remote)) = (
+                local_path(local, entry.path()),
+                remote_path(remote, entry.path()),
+            ) else {
+                let msg = format!(
+                    "{}: Path is not contained in the directory tree",
+                    entry.path()
+                );
+                return Err(io::Error::new(io::ErrorKind::InvalidInput, msg));
+            };
+
+            Ok(Pair {
+                name: entry.path().to_string(),
+                local,
+                remote,
+            })
+        })
+        .collect()
+}
+
+/// Formats the digest as lowercase hexadecimal.
+fn to_hex(digest: &[u8]) -> String {
+    digest.iter().map(|byte| format!("{byte:02x}")).collect()
+}
@@ -0,0 +1,130 @@
+use std::io::Read;
+#[cfg(windows)]
+use std::os::windows::process::CommandExt;
+use std::process::{Child, ExitStatus, Stdio};
+use std::sync::mpsc::Sender;
+use std::sync::{mpsc, Arc, Mutex};
+use std::time::{Duration, Instant};
+use std::{io, mem, process, thread};
+
+use dori_lib::bounded::MAX_VEC_ALLOCATION;
+use dori_lib::operation::{ClientError, ClientResult, CommandLimits, CommandOutput};
+
+/// How often a running program is checked for whether it exited.
+const WAIT_INTERVAL: Duration = Duration::from_millis(10);
+
+/// How long output is still awaited once a program exited.
+const OUTPUT_GRACE_PERIOD: Duration = Duration::from_millis(500);
+
+/// The output of a pipe, captured up to a limit.
+#[derive(Default)]
+struct Captured {
+    data: Vec<u8>,
+    truncated: bool,
+}
+
+/// Executes the program with the given arguments and captures its output within the limits.
+pub fn execute(
+    program: &str,
+    args: &[String],
+    limits: CommandLimits,
+) -> ClientResult<CommandOutput> {
+    let start = Instant::now();
+    let deadline = start + limits.timeout();
+
+    let mut command = process::Command::new(program);
+    command
+        .args(args)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped());
+
+    // The client runs without a console, so console programs would open a window of their own.
+    #[cfg(windows)]
+    command.creation_flags(crate::CREATE_NO_WINDOW);
+
+    let mut child = command
+        .spawn()
+        .map_err(|err| ClientError::io(&err, program))?;
+
+    let limit = usize::try_from(limits.max_output())
+        .map_or(MAX_VEC_ALLOCATION, |limit| limit.min(MAX_VEC_ALLOCATION));
+    let (done, finished) = mpsc::channel();
+
+    let stdout = child
+        .stdout
+        .take()
+        .map(|pipe| capture(pipe, limit, done.clone()));
+    let stderr = child.stderr.take().map(|pipe| capture(pipe, limit, done));
+
+    let (status, timed_out) =
+        wait(&mut child, deadline).map_err(|err| ClientError::io(&err, program))?;
+
+    // Processes started by the program may hold the pipes open, so output is only awaited briefly.
+    let _ = finished.recv_timeout(OUTPUT_GRACE_PERIOD);
+
+    let stdout = take(stdout);
+    let stderr = take(stderr);
+
+    Ok(CommandOutput::new(
+        stdout.data,
+        stderr.data,
+        stdout.truncated || stderr.truncated,
+        status.and_then(|status| status.code()),
+        timed_out,
+        start.elapsed(),
+    ))
+}
+
+/// Waits for the child to exit, or kills it once the deadline passes.
+///
+/// Returns the exit status if it could be waited on, and whether the child was killed.
+fn wait(child: &mut Child, deadline: Instant) -> io::Result<(Option<ExitStatus>, bool)> {
+    loop {
+        if let Some(status) = child.try_wait()? {
+            return Ok((Some(status), false));
+        }
+        if Instant::now() >= deadline {
+            let _ = child.kill();
+            return Ok((child.wait().ok(), true));
+        }
+        thread::sleep(WAIT_INTERVAL);
+    }
+}
+
+/// Reads the pipe on a background thread, keeping up to `limit` bytes.
+///
+/// Output past the limit is read and discarded, so that the program never blocks on a full pipe.
+/// The sender is dropped once the pipe is closed.
+fn capture(
+    mut pipe: impl Read + Send + 'static,
+    limit: usize,
+    done: Sender<()>,
+) -> Arc<Mutex<Captured>> {
+    let captured = Arc::new(Mutex::new(Captured::default()));
+    let shared = Arc::clone(&captured);
+
+    thread::spawn(move || {
+        let mut buf = vec![0; 8192];
+
+        while let Ok(len @ 1..) = pipe.read(&mut buf) {
+            let Ok(mut captured) = shared.lock() else {
+                break;
+            };
+            let kept = len.min(limit - captured.data.len());
+
+            captured.data.extend_from_slice(&buf[..kept]);
+            captured.truncated |= kept < len;
+        }
+        drop(done);
+    });
+    captured
+}
+
+/// Takes the output captured so far.
+fn take(captured: Option<Arc<Mutex<Captured>>>) -> Captured {
+    match captured.as_deref().map(Mutex::lock) {
+        Some(Ok(mut captured)) => mem::take(&mut *captured),
+        _ => Captured::default(),
+    }
+}
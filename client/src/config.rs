@@ -1,24 +1,51 @@
+use std::io;
 use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
 
+use dori_lib::key::CipherKey;
 use serde::{Deserialize, Serialize};
 
+/// The command line flag preceding the path to a key file.
+pub const KEY_FILE_FLAG: &str = "--key-file";
+
+/// The command line flag preceding the path to the pinned TLS certificate of the host.
+pub const TLS_CERT_FLAG: &str = "--tls-cert";
+
 #[derive(Deserialize, Serialize)]
 pub struct ClientConfiguration {
     client_name: String,
     program_name: String,
     host_address: SocketAddr,
-    key: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    key: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    key_file: Option<PathBuf>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    tls_cert: Option<PathBuf>,
 }
 
 impl ClientConfiguration {
     /// Returns this configuration as a set of command line arguments.
-    pub fn to_args(&self) -> [String; 4] {
-        [
+    ///
+    /// The key file, if any, is passed as `--key-file <PATH>` in place of the key. The pinned
+    /// certificate, if any, is passed last as `--tls-cert <PATH>`.
+    pub fn to_args(&self) -> Vec<String> {
+        let mut args = vec![
             self.client_name.clone(),
             self.program_name.clone(),
             self.host_address.to_string(),
-            self.key.clone(),
-        ]
+        ];
+
+        match (&self.key_file, &self.key) {
+            (Some(path), _) => args.extend([KEY_FILE_FLAG.to_string(), path.display().to_string()]),
+            (None, Some(key)) => args.push(key.clone()),
+            (None, None) => {}
+        }
+
+        if let Some(path) = &self.tls_cert {
+            args.extend([TLS_CERT_FLAG.to_string(), path.display().to_string()]);
+        }
+        args
     }
 
     /// Returns the client name.
@@ -38,23 +65,46 @@ impl ClientConfiguration {
         &self.host_address
     }
 
+    /// Returns the path of the pinned TLS certificate of the host.
+    ///
+    /// Returns None if the connection does not use TLS.
+    pub fn tls_cert(&self) -> Option<&Path> {
+        self.tls_cert.as_deref()
+    }
+
     /// Returns the cipher key used for secure streams.
-    pub fn key(&self) -> &str {
-        &self.key
+    ///
+    /// Prefers the binary key material in the key file over the passphrase, which is stretched
+    /// with the salt the host announces during the handshake.
+    pub fn key(&self) -> io::Result<CipherKey> {
+        if let Some(path) = &self.key_file {
+            return CipherKey::from_file(path);
+        }
+        self.key
+            .clone()
+            .map(CipherKey::Passphrase)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "Missing key"))
     }
 
     /// Instantiates a new ClientConfiguration.
+    ///
+    /// Either a key or a key file must be given. If a TLS certificate is given, the connection
+    /// runs over TLS and only a host presenting that certificate is trusted.
     pub const fn new(
         client_name: String,
         program_name: String,
         host_address: SocketAddr,
-        key: String,
+        key: Option<String>,
+        key_file: Option<PathBuf>,
+        tls_cert: Option<PathBuf>,
     ) -> Self {
         Self {
             client_name,
             program_name,
             host_address,
             key,
+            key_file,
+            tls_cert,
         }
     }
 }
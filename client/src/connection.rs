@@ -1,30 +1,48 @@
 use std::io;
-use std::io::Write;
+use std::io::{Read, Write};
+use std::net::TcpStream;
 
-use dori_lib::operation::{Operation, Response};
-use dori_lib::stream::SecureTcpStream;
-use tora::read::ToraRead;
+use dori_lib::operation::{Reply, Request};
+use dori_lib::stream::{ChannelId, SecureStream};
 use tora::write::ToraWrite;
 
 /// A secure connection to the host.
-pub struct HostConnection {
-    stream: SecureTcpStream,
+///
+/// Runs over TCP by default, but any [Read] + [Write] transport can be used.
+pub struct HostConnection<T = TcpStream> {
+    stream: SecureStream<T>,
 }
 
-impl HostConnection {
-    /// Serializes and writes the given response to the host, then flushes the stream.
-    pub fn send_response(&mut self, response: &Response) -> io::Result<()> {
-        self.stream.writes(response)?;
-        self.stream.flush()
+impl<T> HostConnection<T>
+where
+    T: Read + Write,
+{
+    /// Serializes and writes the given reply to the given channel, then flushes the stream.
+    ///
+    /// Replies should be sent on the channel their request arrived on.
+    pub fn send_response(&mut self, channel: ChannelId, reply: &Reply) -> io::Result<()> {
+        self.stream.writes(reply)?;
+        self.stream.flush_to(channel)
     }
 
-    /// Reads and deserializes an operation from the host.
-    pub fn read_operation(&mut self) -> io::Result<Operation> {
-        self.stream.reads()
+    /// Reads and deserializes an operation and its request ID from the host.
+    ///
+    /// Returns the channel the operation arrived on along with it, or None if no operation
+    /// arrived before the transport read timed out.
+    pub fn poll_operation(&mut self) -> io::Result<Option<(ChannelId, Request)>> {
+        self.stream.try_reads_any()
+    }
+
+    /// Sends a heartbeat if one is due and checks whether the host is still alive.
+    ///
+    /// Should be called periodically while an operation takes long to complete. Blocks until the
+    /// transport read times out.
+    pub fn keepalive(&mut self) -> io::Result<()> {
+        self.stream.keepalive()
     }
 
     /// Instantiates a new HostConnection.
-    pub const fn new(stream: SecureTcpStream) -> Self {
+    pub const fn new(stream: SecureStream<T>) -> Self {
         Self { stream }
     }
 }
@@ -0,0 +1,107 @@
+use std::path::Path;
+use std::{fs, io};
+
+use dori_lib::bounded::BoundedVec;
+use dori_lib::operation::{ClientError, ClientResult, DirEntry, FileStat, HashAlgorithm};
+use dori_lib::transfer;
+
+/// Lists the entries of the directory at the given path, sorted by name.
+///
+/// Entries whose status cannot be read, like files removed while listing, are skipped.
+pub fn list_dir(path: &str) -> ClientResult<Vec<DirEntry>> {
+    let mut entries = Vec::new();
+
+    for entry in fs::read_dir(path).map_err(|err| ClientError::io(&err, path))? {
+        let entry = entry.map_err(|err| ClientError::io(&err, path))?;
+
+        if let Ok(stat) = read_stat(&entry.path()) {
+            let name = entry.file_name().to_string_lossy().into_owned();
+            entries.push(DirEntry::new(name, stat));
+        }
+    }
+    entries.sort_by(|a, b| a.name().cmp(b.name()));
+    Ok(entries)
+}
+
+/// Returns the status of the entry at the given path, without following symbolic links.
+pub fn stat(path: &str) -> ClientResult<FileStat> {
+    read_stat(Path::new(path)).map_err(|err| ClientError::io(&err, path))
+}
+
+/// Removes the file or directory at the given path.
+///
+/// A directory that is not empty is only removed if `recursive` is set.
+pub fn remove(path: &str, recursive: bool) -> ClientResult<()> {
+    let metadata = fs::symlink_metadata(path).map_err(|err| ClientError::io(&err, path))?;
+
+    let res = match (metadata.is_dir(), recursive) {
+        (true, true) => fs::remove_dir_all(path),
+        (true, false) => fs::remove_dir(path),
+        (false, _) => fs::remove_file(path),
+    };
+    res.map_err(|err| ClientError::io(&err, path))
+}
+
+/// Moves the entry at the source path to the destination path.
+pub fn rename(src: &str, dest: &str) -> ClientResult<()> {
+    fs::rename(src, dest).map_err(|err| ClientError::io(&err, src))
+}
+
+/// Copies the file or directory at the source path to the destination path.
+///
+/// Directories are only copied if `recursive` is set. Returns the amount of bytes copied.
+pub fn copy(src: &str, dest: &str, recursive: bool) -> ClientResult<u64> {
+    let metadata = fs::metadata(src).map_err(|err| ClientError::io(&err, src))?;
+
+    if !metadata.is_dir() {
+        return fs::copy(src, dest).map_err(|err| ClientError::io(&err, src));
+    }
+    if !recursive {
+        let err = io::Error::new(io::ErrorKind::IsADirectory, "Is a directory");
+        return Err(ClientError::io(&err, src));
+    }
+    copy_dir(Path::new(src), Path::new(dest))
+}
+
+/// Computes the digest of each of the files at the given paths.
+pub fn hash(paths: &[String], algorithm: HashAlgorithm) -> Vec<ClientResult<BoundedVec<u8>>> {
+    paths
+        .iter()
+        .map(|path| {
+            transfer::hash_file(path, algorithm)
+                .map(Into::into)
+                .map_err(|err| ClientError::io(&err, path))
+        })
+        .collect()
+}
+
+/// Copies the directory tree at the source path to the destination path.
+fn copy_dir(src: &Path, dest: &Path) -> ClientResult<u64> {
+    let entries = transfer::walk_tree(src).map_err(|err| ClientError::io(&err, src))?;
+    let mut copied = 0;
+
+    fs::create_dir_all(dest).map_err(|err| ClientError::io(&err, dest))?;
+
+    for entry in entries {
+        let from = src.join(entry.path());
+        let to = dest.join(entry.path());
+
+        if entry.is_dir() {
+            fs::create_dir_all(&to).map_err(|err| ClientError::io(&err, &to))?;
+        } else {
+            copied += fs::copy(&from, &to).map_err(|err| ClientError::io(&err, &from))?;
+        }
+    }
+    Ok(copied)
+}
+
+/// Reads the status of the entry at the given path, without following symbolic links.
+fn read_stat(path: &Path) -> io::Result<FileStat> {
+    let metadata = fs::symlink_metadata(path)?;
+
+    let link_target = match metadata.is_symlink() {
+        true => Some(fs::read_link(path)?.to_string_lossy().into_owned()),
+        false => None,
+    };
+    Ok(FileStat::new(&metadata, link_target))
+}
@@ -0,0 +1,43 @@
+use std::time::Duration;
+
+use dori_lib::operation::{DiskInfo, OsInfo, SystemInfo};
+use sysinfo::{CpuRefreshKind, Disks, System};
+
+/// Collects an inventory of the system the client runs on.
+pub fn collect() -> SystemInfo {
+    let mut system = System::new();
+    system.refresh_memory();
+    system.refresh_cpu_list(CpuRefreshKind::nothing());
+
+    let os = OsInfo::new(
+        System::name(),
+        System::os_version(),
+        System::kernel_version(),
+        System::host_name(),
+        System::cpu_arch(),
+    );
+
+    let disks = Disks::new_with_refreshed_list()
+        .list()
+        .iter()
+        .map(|disk| {
+            DiskInfo::new(
+                disk.name().to_string_lossy().into_owned(),
+                disk.mount_point().display().to_string(),
+                disk.file_system().to_string_lossy().into_owned(),
+                disk.total_space(),
+                disk.available_space(),
+            )
+        })
+        .collect();
+
+    SystemInfo::new(
+        os,
+        system.cpus().len() as u32,
+        system.total_memory(),
+        system.available_memory(),
+        disks,
+        Duration::from_secs(System::uptime()),
+        env!("CARGO_PKG_VERSION").to_string(),
+    )
+}
@@ -1,16 +1,40 @@
 #![windows_subsystem = "windows"]
 
+use std::io::{Read, Write};
 use std::net::{SocketAddr, TcpStream};
-use std::{env, fs, io};
+use std::time::Duration;
+use std::{env, fs, io, thread};
 
-use dori_client::config::ClientConfiguration;
+use dori_client::config::{ClientConfiguration, KEY_FILE_FLAG, TLS_CERT_FLAG};
 use dori_lib::handshake;
 use dori_lib::handshake::Handshake;
-use dori_lib::operation::{Operation, Response};
+use dori_lib::operation::{ClientError, Operation, Reply, Response};
+use dori_lib::stream::CONTROL_CHANNEL;
+#[cfg(feature = "tls")]
+use dori_lib::tls;
+#[cfg(feature = "tls")]
+use dori_lib::tls::{ClientTransport, MaybeTlsStream};
 
 use crate::connection::HostConnection;
+use crate::shell::ShellSessions;
 
+mod command;
 mod connection;
+mod filesystem;
+mod inventory;
+mod processes;
+mod shell;
+mod transfer;
+
+/// The read timeout of the established connection.
+const POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+/// The read timeout of the established connection while shell sessions are running.
+const SHELL_POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// Process creation flag that keeps console programs from opening a window.
+#[cfg(windows)]
+const CREATE_NO_WINDOW: u32 = 0x0800_0000;
 
 fn parse_arg_config() -> Result<ClientConfiguration, &'static str> {
     let mut args = env::args().skip(1);
@@ -19,39 +43,179 @@ fn parse_arg_config() -> Result<ClientConfiguration, &'static str> {
     let program_name = args.next().ok_or("Missing program name")?;
     let host_address = args.next().ok_or("Missing host address")?;
 
-    let host_address: SocketAddr = host_address
-        .parse()
-        .map_err(|_| "Invalid host address")?;
+    let host_address: SocketAddr = host_address.parse().map_err(|_| "Invalid host address")?;
 
     let key = args.next().ok_or("Missing key")?;
 
+    let (key, key_file) = match key.as_str() {
+        KEY_FILE_FLAG => (None, Some(args.next().ok_or("Missing key file")?.into())),
+        _ => (Some(key), None),
+    };
+
+    let tls_cert = match args.next().as_deref() {
+        Some(TLS_CERT_FLAG) => Some(args.next().ok_or("Missing TLS certificate")?.into()),
+        Some(_) => return Err("Unexpected argument"),
+        None => None,
+    };
+
     Ok(ClientConfiguration::new(
         client_name,
         program_name,
         host_address,
         key,
+        key_file,
+        tls_cert,
     ))
 }
 
-fn run(config: &ClientConfiguration) -> io::Result<()> {
-    let stream = TcpStream::connect(config.host_address())?;
-    let handshake = Handshake::new(config.client_name().to_string(), config.key().to_string());
+#[cfg(feature = "tls")]
+fn secure(config: &ClientConfiguration, stream: TcpStream) -> io::Result<ClientTransport> {
+    match config.tls_cert() {
+        Some(path) => tls::connect(tls::client_config(tls::load_certificate(path)?)?, stream),
+        None => Ok(MaybeTlsStream::Plain(stream)),
+    }
+}
+
+#[cfg(not(feature = "tls"))]
+fn secure(config: &ClientConfiguration, stream: TcpStream) -> io::Result<TcpStream> {
+    match config.tls_cert() {
+        Some(_) => Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "TLS requires dori-client to be built with the tls feature",
+        )),
+        None => Ok(stream),
+    }
+}
 
-    let stream = handshake::perform_client_handshake(stream, handshake)?
+/// Runs the function on another thread and keeps the connection alive until it returns.
+fn keep_alive_while<T, R, F>(conn: &mut HostConnection<T>, f: F) -> io::Result<R>
+where
+    T: Read + Write,
+    R: Send + 'static,
+    F: FnOnce() -> R + Send + 'static,
+{
+    let handle = thread::spawn(f);
+
+    while !handle.is_finished() {
+        conn.keepalive()?;
+    }
+    handle
+        .join()
+        .map_err(|_| io::Error::other("Operation panicked"))
+}
+
+fn run(config: &ClientConfiguration, handshake: &mut Handshake) -> io::Result<()> {
+    let socket = TcpStream::connect(config.host_address())?;
+    let transport = secure(config, socket.try_clone()?)?;
+
+    let stream = handshake::perform_client_handshake(transport, handshake)?
         .ok_or(io::ErrorKind::ConnectionRefused)?;
 
+    // Idle reads time out regularly, so that heartbeats are exchanged and a dead host is noticed.
+    socket.set_read_timeout(Some(POLL_INTERVAL))?;
+
     let mut conn = HostConnection::new(stream);
+    let mut shells = ShellSessions::new();
+    let mut poll_interval = POLL_INTERVAL;
 
     loop {
-        let response = match conn.read_operation()? {
-            Operation::Upload(op) => {
-                let res = fs::write(op.path(), op.content()).map_err(|e| e.to_string());
-                Response::Upload(res)
+        for (session, response) in shells.drain() {
+            conn.send_response(CONTROL_CHANNEL, &Reply::new(session, response))?;
+        }
+
+        // Shell output is only forwarded between reads, so reads time out quickly during sessions.
+        let interval = match shells.is_empty() {
+            true => POLL_INTERVAL,
+            false => SHELL_POLL_INTERVAL,
+        };
+        if interval != poll_interval {
+            socket.set_read_timeout(Some(interval))?;
+            poll_interval = interval;
+        }
+
+        let Some((channel, request)) = conn.poll_operation()? else {
+            continue;
+        };
+        let id = request.id();
+
+        let response = match request.into_operation() {
+            Operation::BeginUpload(path, size) => {
+                Response::BeginUpload(transfer::begin_upload(&path, size))
+            }
+            Operation::UploadChunk(chunk) => Response::UploadChunk(transfer::write_chunk(&chunk)),
+            Operation::FinishUpload(path, digest, mode) => {
+                let res = keep_alive_while(&mut conn, move || {
+                    transfer::finish_upload(&path, &digest, mode)
+                })?;
+                Response::FinishUpload(res)
+            }
+            Operation::Download(path) => {
+                let res = keep_alive_while(&mut conn, move || transfer::begin_download(&path))?;
+                Response::Download(res)
+            }
+            Operation::DownloadChunk(path, offset) => {
+                Response::DownloadChunk(transfer::read_chunk(&path, offset).map(Into::into))
+            }
+            Operation::ListTree(path) => {
+                let res = keep_alive_while(&mut conn, move || transfer::list_tree(&path))?;
+                Response::ListTree(res.map(Into::into))
+            }
+            Operation::Command(program, args, limits) => {
+                let res =
+                    keep_alive_while(&mut conn, move || command::execute(&program, &args, limits))?;
+                Response::Command(res)
+            }
+            Operation::CreateDirectory(path) => {
+                let res = fs::create_dir_all(&path).map_err(|err| ClientError::io(&err, &path));
+                Response::CreateDirectory(res)
+            }
+            Operation::ListDirectory(path) => {
+                Response::ListDirectory(filesystem::list_dir(&path).map(Into::into))
+            }
+            Operation::Stat(path) => Response::Stat(filesystem::stat(&path)),
+            Operation::Remove(path, recursive) => {
+                let res =
+                    keep_alive_while(&mut conn, move || filesystem::remove(&path, recursive))?;
+                Response::Remove(res)
+            }
+            Operation::Rename(src, dest) => Response::Rename(filesystem::rename(&src, &dest)),
+            Operation::Copy(src, dest, recursive) => {
+                let res =
+                    keep_alive_while(&mut conn, move || filesystem::copy(&src, &dest, recursive))?;
+                Response::Copy(res)
+            }
+            Operation::Hash(paths, algorithm) => {
+                let digests =
+                    keep_alive_while(&mut conn, move || filesystem::hash(&paths, algorithm))?;
+                Response::Hash(digests.into())
+            }
+            Operation::SystemInfo => Response::SystemInfo(inventory::collect()),
+            Operation::ListProcesses => {
+                let list = keep_alive_while(&mut conn, processes::list)?;
+                Response::ListProcesses(list.into())
+            }
+            Operation::Kill(target, signal) => {
+                Response::Kill(processes::kill(&target, signal).map(Into::into))
+            }
+            Operation::OpenShell(options) => Response::OpenShell(shells.open(id, &options)),
+
+            // Operations on running sessions are not replied to.
+            Operation::ShellInput(session, data) => {
+                shells.write(session, &data);
+                continue;
+            }
+            Operation::ResizeShell(session, cols, rows) => {
+                shells.resize(session, cols, rows);
+                continue;
+            }
+            Operation::CloseShell(session) => {
+                shells.close(session);
+                continue;
             }
             Operation::Ping => Response::Pong,
-            _ => return Err(io::ErrorKind::Unsupported.into())
+            _ => return Err(io::ErrorKind::Unsupported.into()),
         };
-        conn.send_response(&response)?;
+        conn.send_response(channel, &Reply::new(id, response))?;
     }
 }
 
@@ -64,8 +228,18 @@ fn main() {
         }
     };
 
+    let key = match config.key() {
+        Ok(key) => key,
+        Err(err) => {
+            eprintln!("Program error: {err}");
+            return;
+        }
+    };
+    // The handshake keeps the passphrase stretched with the host's salt across reconnects.
+    let mut handshake = Handshake::new(config.client_name().to_string(), key);
+
     loop {
-        let err = run(&config);
+        let err = run(&config, &mut handshake);
 
         #[cfg(debug_assertions)]
         println!("Error: {err:#?}");
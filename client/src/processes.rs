@@ -0,0 +1,142 @@
+#[cfg(windows)]
+use std::os::windows::process::CommandExt;
+#[cfg(not(unix))]
+use std::process;
+use std::{io, thread};
+
+use dori_lib::operation::{ClientResult, ProcessInfo, ProcessTarget, Signal};
+use sysinfo::{
+    Pid, ProcessRefreshKind, ProcessesToUpdate, System, UpdateKind, MINIMUM_CPU_UPDATE_INTERVAL,
+};
+
+/// Lists the processes running on the client, sorted by PID.
+///
+/// CPU usage is measured over a short interval, so this blocks for a moment.
+pub fn list() -> Vec<ProcessInfo> {
+    let refresh = ProcessRefreshKind::nothing()
+        .with_cpu()
+        .with_memory()
+        .with_cmd(UpdateKind::OnlyIfNotSet);
+
+    let mut system = System::new();
+    system.refresh_processes_specifics(ProcessesToUpdate::All, true, refresh);
+    thread::sleep(MINIMUM_CPU_UPDATE_INTERVAL);
+    system.refresh_processes_specifics(ProcessesToUpdate::All, true, refresh);
+
+    let mut processes: Vec<_> = system
+        .processes()
+        .values()
+        .map(|process| {
+            ProcessInfo::new(
+                process.pid().as_u32(),
+                process.parent().map(Pid::as_u32),
+                process.name().to_string_lossy().into_owned(),
+                process
+                    .cmd()
+                    .iter()
+                    .map(|arg| arg.to_string_lossy().into_owned())
+                    .collect(),
+                process.cpu_usage(),
+                process.memory(),
+            )
+        })
+        .collect();
+
+    processes.sort_by_key(ProcessInfo::pid);
+    processes
+}
+
+/// Terminates the processes the target matches with the given signal, or the platform's default
+/// way if there is none.
+///
+/// Returns the PIDs of the processes that were signaled. Fails with the error of the first
+/// process if none could be signaled.
+pub fn kill(target: &ProcessTarget, signal: Option<Signal>) -> ClientResult<Vec<u32>> {
+    let pids: Vec<_> = match target {
+        ProcessTarget::Pid(pid) => vec![*pid],
+        ProcessTarget::Name(name) => {
+            let mut system = System::new();
+            let refresh = ProcessRefreshKind::nothing();
+            system.refresh_processes_specifics(ProcessesToUpdate::All, true, refresh);
+
+            system
+                .processes_by_exact_name(name.as_ref())
+                .map(|process| process.pid().as_u32())
+                .collect()
+        }
+    };
+    if pids.is_empty() {
+        let msg = format!("No process matches {target}");
+        return Err(io::Error::new(io::ErrorKind::NotFound, msg).into());
+    }
+    let mut signaled = Vec::new();
+    let mut error = None;
+
+    for pid in pids {
+        match send_signal(pid, signal) {
+            Ok(()) => signaled.push(pid),
+            Err(err) => {
+                error.get_or_insert(err);
+            }
+        }
+    }
+
+    match error {
+        Some(err) if signaled.is_empty() => Err(err.into()),
+        _ => {
+            signaled.sort_unstable();
+            Ok(signaled)
+        }
+    }
+}
+
+/// Sends the signal to the process with the given ID, or SIGTERM if there is none.
+#[cfg(unix)]
+fn send_signal(pid: u32, signal: Option<Signal>) -> io::Result<()> {
+    // PID 0 and negative PIDs would signal whole process groups.
+    let pid = match libc::pid_t::try_from(pid) {
+        Ok(pid) if pid > 0 => pid,
+        _ => return Err(io::Error::new(io::ErrorKind::InvalidInput, "Invalid PID")),
+    };
+    let signal = match signal.unwrap_or(Signal::Terminate) {
+        Signal::Hangup => libc::SIGHUP,
+        Signal::Interrupt => libc::SIGINT,
+        Signal::Quit => libc::SIGQUIT,
+        Signal::Kill => libc::SIGKILL,
+        Signal::Terminate => libc::SIGTERM,
+        Signal::User1 => libc::SIGUSR1,
+        Signal::User2 => libc::SIGUSR2,
+        Signal::Stop => libc::SIGSTOP,
+        Signal::Continue => libc::SIGCONT,
+    };
+
+    // SAFETY: kill has no memory safety requirements.
+    match unsafe { libc::kill(pid, signal) } {
+        0 => Ok(()),
+        _ => Err(io::Error::last_os_error()),
+    }
+}
+
+/// Forcibly terminates the process with the given ID, which is all SIGKILL maps to on platforms
+/// without signals.
+#[cfg(not(unix))]
+fn send_signal(pid: u32, signal: Option<Signal>) -> io::Result<()> {
+    if let Some(signal) = signal.filter(|signal| *signal != Signal::Kill) {
+        let msg = format!("{signal} is not supported by the client");
+        return Err(io::Error::new(io::ErrorKind::Unsupported, msg));
+    }
+    let mut command = process::Command::new("taskkill");
+    command.args(["/PID", &pid.to_string(), "/F"]);
+
+    #[cfg(windows)]
+    command.creation_flags(crate::CREATE_NO_WINDOW);
+
+    let output = command.output()?;
+
+    match output.status.success() {
+        true => Ok(()),
+        false => Err(io::Error::other(
+            String::from_utf8_lossy(&output.stderr).trim().to_string(),
+        )),
+    }
+}
@@ -0,0 +1,182 @@
+use std::collections::HashMap;
+use std::io::{Read, Write};
+use std::sync::mpsc;
+use std::sync::mpsc::{Receiver, Sender};
+use std::thread;
+use std::time::Duration;
+
+use dori_lib::operation::{ClientError, ClientResult, RequestId, Response, ShellOptions};
+use portable_pty::{native_pty_system, Child, ChildKiller, CommandBuilder, MasterPty, PtySize};
+
+/// The maximum amount of output sent in a single reply.
+const OUTPUT_CHUNK_LEN: usize = 16 * 1024;
+
+/// How long the output of an exited shell is still forwarded before its exit is reported.
+const EXIT_GRACE_PERIOD: Duration = Duration::from_millis(500);
+
+/// A running shell session.
+struct Session {
+    master: Box<dyn MasterPty + Send>,
+    writer: Box<dyn Write + Send>,
+    killer: Box<dyn ChildKiller + Send + Sync>,
+}
+
+/// The interactive shell sessions of a connection.
+///
+/// Every shell runs in a pseudo-terminal, so stdout and stderr arrive as a single stream. Its
+/// output and exit are collected by background threads and forwarded with [drain](Self::drain).
+pub struct ShellSessions {
+    sessions: HashMap<RequestId, Session>,
+    events: Receiver<(RequestId, Response)>,
+    sender: Sender<(RequestId, Response)>,
+}
+
+impl ShellSessions {
+    /// Instantiates a new, empty ShellSessions.
+    pub fn new() -> Self {
+        let (sender, events) = mpsc::channel();
+
+        Self {
+            sessions: HashMap::new(),
+            events,
+            sender,
+        }
+    }
+
+    /// Returns true if no session is running.
+    pub fn is_empty(&self) -> bool {
+        self.sessions.is_empty()
+    }
+
+    /// Starts a shell session identified by the given request ID.
+    pub fn open(&mut self, id: RequestId, options: &ShellOptions) -> ClientResult<()> {
+        let pair = native_pty_system()
+            .openpty(pty_size(options.cols(), options.rows()))
+            .map_err(other)?;
+
+        let command = match options.program() {
+            Some(program) => CommandBuilder::new(program),
+            None => CommandBuilder::new_default_prog(),
+        };
+        let child = pair.slave.spawn_command(command).map_err(other)?;
+
+        // The reader only sees the end of the output once no handle to the terminal is left.
+        drop(pair.slave);
+
+        let session = Session {
+            writer: pair.master.take_writer().map_err(other)?,
+            killer: child.clone_killer(),
+            master: pair.master,
+        };
+        let reader = session.master.try_clone_reader().map_err(other)?;
+
+        self.spawn_forwarders(id, reader, child);
+        self.sessions.insert(id, session);
+        Ok(())
+    }
+
+    /// Writes the data to the input of the session with the given ID, if it is running.
+    pub fn write(&mut self, id: RequestId, data: &[u8]) {
+        let Some(session) = self.sessions.get_mut(&id) else {
+            return;
+        };
+        if session
+            .writer
+            .write_all(data)
+            .and_then(|_| session.writer.flush())
+            .is_err()
+        {
+            let _ = session.killer.kill();
+        }
+    }
+
+    /// Resizes the terminal of the session with the given ID, if it is running.
+    pub fn resize(&mut self, id: RequestId, cols: u16, rows: u16) {
+        if let Some(session) = self.sessions.get(&id) {
+            let _ = session.master.resize(pty_size(cols, rows));
+        }
+    }
+
+    /// Terminates the shell of the session with the given ID, if it is running.
+    ///
+    /// The session ends once the exit of the shell is forwarded.
+    pub fn close(&mut self, id: RequestId) {
+        if let Some(session) = self.sessions.get_mut(&id) {
+            let _ = session.killer.kill();
+        }
+    }
+
+    /// Returns the output and exits collected since the last call, tagged with their session IDs.
+    ///
+    /// Sessions whose exit is returned are removed, and nothing is returned for them afterwards.
+    pub fn drain(&mut self) -> Vec<(RequestId, Response)> {
+        let mut events = Vec::new();
+
+        for (id, response) in self.events.try_iter() {
+            // Output may still arrive after the exit, like from a background job that holds the
+            // terminal open, but the host no longer expects replies to the session.
+            if !self.sessions.contains_key(&id) {
+                continue;
+            }
+            if let Response::ShellExit(_) = response {
+                self.sessions.remove(&id);
+            }
+            events.push((id, response));
+        }
+        events
+    }
+
+    /// Spawns the threads that forward the output and exit of a session.
+    fn spawn_forwarders(
+        &self,
+        id: RequestId,
+        mut reader: Box<dyn Read + Send>,
+        mut child: Box<dyn Child + Send + Sync>,
+    ) {
+        let (done, finished) = mpsc::channel::<()>();
+        let sender = self.sender.clone();
+
+        thread::spawn(move || {
+            let mut buf = vec![0; OUTPUT_CHUNK_LEN];
+
+            // Reading fails rather than ending on some platforms once the shell exits.
+            while let Ok(len @ 1..) = reader.read(&mut buf) {
+                let output = Response::ShellOutput(buf[..len].to_vec().into());
+
+                if sender.send((id, output)).is_err() {
+                    break;
+                }
+            }
+            drop(done);
+        });
+
+        let sender = self.sender.clone();
+
+        thread::spawn(move || {
+            let code = child.wait().ok().and_then(|status| match status.signal() {
+                Some(_) => None,
+                None => Some(status.exit_code() as i32),
+            });
+
+            // Some platforms only end the output once the terminal is closed, which happens when
+            // the session is removed, so the exit is not held back indefinitely.
+            let _ = finished.recv_timeout(EXIT_GRACE_PERIOD);
+            let _ = sender.send((id, Response::ShellExit(code)));
+        });
+    }
+}
+
+/// Returns the size of a terminal with the given columns and rows.
+const fn pty_size(cols: u16, rows: u16) -> PtySize {
+    PtySize {
+        rows,
+        cols,
+        pixel_width: 0,
+        pixel_height: 0,
+    }
+}
+
+/// Converts an error of the pseudo-terminal into a [ClientError].
+fn other(err: impl ToString) -> ClientError {
+    ClientError::Other(err.to_string())
+}
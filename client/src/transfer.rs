@@ -0,0 +1,128 @@
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::{fs, io};
+
+use dori_lib::operation::{ClientError, ClientResult, FileChunk, FileMetadata, TreeEntry};
+use dori_lib::transfer;
+use dori_lib::transfer::CHUNK_LEN;
+
+/// Starts or resumes receiving a file of the given size at the given path.
+///
+/// Returns the amount of bytes that were already received by an interrupted upload. Received
+/// data is discarded if it is longer than the file.
+pub fn begin_upload(path: &str, size: u64) -> ClientResult<u64> {
+    let partial = transfer::partial_path(path);
+    let file = OpenOptions::new()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .open(&partial)
+        .map_err(|err| ClientError::io(&err, path))?;
+
+    let received = file
+        .metadata()
+        .map_err(|err| ClientError::io(&err, &partial))?
+        .len();
+
+    if received <= size {
+        return Ok(received);
+    }
+    file.set_len(0)
+        .map_err(|err| ClientError::io(&err, &partial))?;
+    Ok(0)
+}
+
+/// Appends the chunk to the partially received file.
+///
+/// Returns the amount of bytes received so far. Fails with [io::ErrorKind::InvalidInput] if the
+/// chunk does not start where the received data ends.
+pub fn write_chunk(chunk: &FileChunk) -> ClientResult<u64> {
+    let partial = transfer::partial_path(chunk.path());
+    let mut file = OpenOptions::new()
+        .append(true)
+        .open(&partial)
+        .map_err(|err| ClientError::io(&err, &partial))?;
+
+    let received = file
+        .metadata()
+        .map_err(|err| ClientError::io(&err, &partial))?
+        .len();
+
+    if received != chunk.offset() {
+        let err = io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!(
+                "Chunk starts at {}, but {received} bytes were received",
+                chunk.offset()
+            ),
+        );
+        return Err(ClientError::io(&err, &partial));
+    }
+
+    file.write_all(chunk.data())
+        .map_err(|err| ClientError::io(&err, &partial))?;
+    Ok(received + chunk.data().len() as u64)
+}
+
+/// Verifies the partially received file against the digest and moves it to the given path,
+/// applying the Unix mode if one is given.
+///
+/// Deletes the partially received file and fails with [io::ErrorKind::InvalidData] if it does
+/// not match.
+pub fn finish_upload(path: &str, digest: &[u8], mode: Option<u32>) -> ClientResult<()> {
+    let partial = transfer::partial_path(path);
+    let actual = transfer::digest_file(&partial).map_err(|err| ClientError::io(&err, &partial))?;
+
+    if actual != digest {
+        let _ = fs::remove_file(&partial);
+        let err = io::Error::new(io::ErrorKind::InvalidData, "File digest does not match");
+        return Err(ClientError::io(&err, path));
+    }
+    fs::rename(&partial, path).map_err(|err| ClientError::io(&err, path))?;
+
+    match mode {
+        Some(mode) => {
+            transfer::set_file_mode(path, mode).map_err(|err| ClientError::io(&err, path))
+        }
+        None => Ok(()),
+    }
+}
+
+/// Returns the metadata of the file at the given path, which starts downloading it.
+///
+/// Hashes the whole file, so this may take a while for large files.
+pub fn begin_download(path: &str) -> ClientResult<FileMetadata> {
+    let metadata = fs::metadata(path).map_err(|err| ClientError::io(&err, path))?;
+
+    if metadata.is_dir() {
+        let err = io::Error::new(io::ErrorKind::IsADirectory, "Is a directory");
+        return Err(ClientError::io(&err, path));
+    }
+    let digest = transfer::digest_file(path).map_err(|err| ClientError::io(&err, path))?;
+
+    Ok(FileMetadata::new(
+        metadata.len(),
+        metadata.modified().ok(),
+        metadata.permissions().readonly(),
+        transfer::file_mode(&metadata),
+        digest,
+    ))
+}
+
+/// Reads the chunk of the file at the given path that starts at the given offset.
+///
+/// Returns an empty chunk if the offset is at or past the end of the file.
+pub fn read_chunk(path: &str, offset: u64) -> ClientResult<Vec<u8>> {
+    let mut file = File::open(path).map_err(|err| ClientError::io(&err, path))?;
+    let mut data = Vec::with_capacity(CHUNK_LEN);
+
+    file.seek(SeekFrom::Start(offset))
+        .and_then(|_| file.take(CHUNK_LEN as u64).read_to_end(&mut data))
+        .map_err(|err| ClientError::io(&err, path))?;
+    Ok(data)
+}
+
+/// Lists the files and directories below the directory at the given path.
+pub fn list_tree(path: &str) -> ClientResult<Vec<TreeEntry>> {
+    transfer::walk_tree(path).map_err(|err| ClientError::io(&err, path))
+}
@@ -0,0 +1,7 @@
+pub use connection::{ClientConnection, HostConnection};
+pub use handshake::{perform_client_handshake, perform_host_handshake};
+pub use stream::SecureStream;
+
+mod connection;
+mod handshake;
+mod stream;
@@ -0,0 +1,162 @@
+use std::collections::HashMap;
+use std::io;
+use std::time::Duration;
+
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::net::TcpStream;
+
+use crate::asynchronous;
+use crate::asynchronous::SecureStream;
+use crate::handshake::{Handshake, HostRejectionReason};
+use crate::key::CipherKey;
+use crate::operation::{Operation, Reply, Request, RequestId, Response};
+use crate::stream::{ChannelId, CONTROL_CHANNEL};
+
+/// An asynchronous, secure connection to the client.
+///
+/// A single host task can drive many of these connections concurrently.
+///
+/// Every operation is sent with a new request ID, so several operations can be in flight at once.
+/// Replies that arrive before they are asked for are kept until they are.
+pub struct ClientConnection<T = TcpStream> {
+    stream: SecureStream<T>,
+    next_id: RequestId,
+    replies: HashMap<RequestId, Response>,
+}
+
+impl<T> ClientConnection<T>
+where
+    T: AsyncRead + AsyncWrite + Unpin,
+{
+    /// Performs a handshake with the client over the given transport.
+    ///
+    /// Only accepts clients with the given name.
+    pub async fn establish(
+        transport: T,
+        client_name: &str,
+        key: &CipherKey,
+    ) -> io::Result<Result<Self, HostRejectionReason>> {
+        let handshake = Handshake::new(client_name.to_string(), key.clone());
+        let result = asynchronous::perform_host_handshake(transport, handshake).await?;
+
+        Ok(result.map(|stream| Self {
+            stream,
+            next_id: 0,
+            replies: HashMap::new(),
+        }))
+    }
+
+    /// Sets the maximum size of a frame received from the client.
+    pub fn set_max_frame_size(&mut self, max_frame_size: u32) {
+        self.stream.set_max_frame_size(max_frame_size);
+    }
+
+    /// Sets the amount of bytes and time after which the session is rekeyed.
+    pub fn set_rekey_limits(&mut self, after_bytes: u64, interval: Duration) {
+        self.stream.set_rekey_after_bytes(after_bytes);
+        self.stream.set_rekey_interval(interval);
+    }
+
+    /// Sets the heartbeat interval and the amount of missed heartbeats after which the client is
+    /// considered dead.
+    pub fn set_heartbeat(&mut self, interval: Duration, max_missed: u32) {
+        self.stream.set_heartbeat_interval(interval);
+        self.stream.set_max_missed_heartbeats(max_missed);
+    }
+
+    /// Serializes and writes the given operation to the inner stream.
+    /// Flushes the inner stream.
+    ///
+    /// Returns the ID of the request, which is used to read its response.
+    pub async fn send_operation(&mut self, operation: Operation) -> io::Result<RequestId> {
+        self.send_operation_on(CONTROL_CHANNEL, operation).await
+    }
+
+    /// Serializes and writes the given operation to the given channel of the inner stream.
+    /// Flushes the inner stream.
+    ///
+    /// The client replies on the same channel. Returns the ID of the request, which is used to
+    /// read its response.
+    pub async fn send_operation_on(
+        &mut self,
+        channel: ChannelId,
+        operation: Operation,
+    ) -> io::Result<RequestId> {
+        let id = self.next_id;
+
+        self.stream.writes(&Request::new(id, operation))?;
+        self.stream.flush_to(channel).await?;
+        self.next_id += 1;
+        Ok(id)
+    }
+
+    /// Reads and deserializes the [Response] to the request with the given ID.
+    ///
+    /// Replies to other requests that are read in the meantime are kept for later.
+    pub async fn read_response(&mut self, id: RequestId) -> io::Result<Response> {
+        if let Some(response) = self.replies.remove(&id) {
+            return Ok(response);
+        }
+
+        loop {
+            let (_, reply): (_, Reply) = self.stream.reads_any().await?;
+
+            if reply.id() == id {
+                return Ok(reply.into_response());
+            }
+            if reply.id() >= self.next_id || self.replies.contains_key(&reply.id()) {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "Client replied to an unknown request",
+                ));
+            }
+            self.replies.insert(reply.id(), reply.into_response());
+        }
+    }
+
+    /// Sends a heartbeat if one is due and checks whether the client is still alive.
+    ///
+    /// Should be called periodically while no operation is in flight.
+    pub async fn keepalive(&mut self) -> io::Result<()> {
+        self.stream.keepalive().await
+    }
+}
+
+/// An asynchronous, secure connection to the host.
+pub struct HostConnection<T = TcpStream> {
+    stream: SecureStream<T>,
+}
+
+impl<T> HostConnection<T>
+where
+    T: AsyncRead + AsyncWrite + Unpin,
+{
+    /// Performs a handshake with the host over the given transport.
+    ///
+    /// The handshake keeps the key stretched with the host's salt for later connections. Returns
+    /// None if the host rejected the connection.
+    pub async fn establish(transport: T, handshake: &mut Handshake) -> io::Result<Option<Self>> {
+        let stream = asynchronous::perform_client_handshake(transport, handshake).await?;
+        Ok(stream.map(Self::new))
+    }
+
+    /// Serializes and writes the given reply to the given channel, then flushes the stream.
+    ///
+    /// Replies should be sent on the channel their request arrived on.
+    pub async fn send_response(&mut self, channel: ChannelId, reply: &Reply) -> io::Result<()> {
+        self.stream.writes(reply)?;
+        self.stream.flush_to(channel).await
+    }
+
+    /// Reads and deserializes an operation and its request ID from the host.
+    ///
+    /// Returns the channel the operation arrived on along with it.
+    pub async fn read_operation(&mut self) -> io::Result<(ChannelId, Request)> {
+        self.stream.reads_any().await
+    }
+
+    /// Instantiates a new HostConnection.
+    pub const fn new(stream: SecureStream<T>) -> Self {
+        Self { stream }
+    }
+}
@@ -0,0 +1,149 @@
+use std::io;
+use std::io::Cursor;
+
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::task;
+use tora::read::{FromReader, ToraRead};
+use tora::write::{SerializeIo, ToraWrite};
+
+use crate::asynchronous::SecureStream;
+use crate::handshake;
+use crate::handshake::{Exchange, Handshake, Hello, HostRejectionReason, PendingHello};
+
+/// The maximum length of an unencrypted handshake message.
+const MAX_MESSAGE_LEN: usize = 256;
+
+/// Serializes the given message and writes it to the transport.
+async fn write_message<T, S>(stream: &mut T, message: &S) -> io::Result<()>
+where
+    T: AsyncWrite + Unpin,
+    S: SerializeIo,
+{
+    let mut buf = Vec::new();
+    buf.writes(message)?;
+
+    stream.write_all(&buf).await?;
+    stream.flush().await
+}
+
+/// Reads an unencrypted handshake message from the transport.
+///
+/// Handshake messages are not length-prefixed, so the message is read byte by byte until it can be
+/// deserialized. This never consumes bytes of the following message.
+async fn read_message<T, F>(stream: &mut T) -> io::Result<F>
+where
+    T: AsyncRead + Unpin,
+    F: FromReader,
+{
+    let mut buf = Vec::new();
+
+    loop {
+        match Cursor::new(&buf).reads() {
+            Ok(message) => return Ok(message),
+            Err(err) if err.kind() != io::ErrorKind::UnexpectedEof => return Err(err),
+            Err(_) if buf.len() >= MAX_MESSAGE_LEN => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "Handshake message exceeds the size limit",
+                ));
+            }
+            Err(_) => buf.push(stream.read_u8().await?),
+        }
+    }
+}
+
+/// Exchanges [Hello] messages with the peer.
+///
+/// The client stretches its passphrase with the host's salt on tokio's blocking thread pool, unless
+/// it already did so in an earlier handshake.
+async fn exchange_hellos<T>(
+    stream: &mut T,
+    form: &mut Handshake,
+    is_host: bool,
+) -> io::Result<Exchange>
+where
+    T: AsyncRead + AsyncWrite + Unpin,
+{
+    let pending = PendingHello::new(form);
+    write_message(stream, pending.hello()).await?;
+
+    let peer_hello: Hello = read_message(stream).await?;
+
+    if !is_host {
+        let key = form.key().clone();
+        let salt = peer_hello.key_salt();
+        let key = task::spawn_blocking(move || key.stretch(salt))
+            .await
+            .map_err(io::Error::other)??;
+
+        form.set_key(key);
+    }
+    pending.complete(form, peer_hello, is_host)
+}
+
+/// Performs a handshake with the host over the given transport and if successful, returns a
+/// secure stream.
+///
+/// Asynchronous counterpart of
+/// [perform_client_handshake](crate::handshake::perform_client_handshake). Must be called within a
+/// tokio runtime.
+///
+/// Returns None if the host rejected the connection.
+pub async fn perform_client_handshake<T>(
+    mut stream: T,
+    form: &mut Handshake,
+) -> io::Result<Option<SecureStream<T>>>
+where
+    T: AsyncRead + AsyncWrite + Unpin,
+{
+    let exchange = exchange_hellos(&mut stream, form, false).await?;
+    write_message(&mut stream, &exchange.client_proof()).await?;
+
+    let Some(protocol) = exchange.accept_host_reply(read_message(&mut stream).await?)? else {
+        return Ok(None);
+    };
+
+    let (send_key, recv_key) = exchange.client_keys();
+    let mut secure_stream = SecureStream::new(stream, send_key, recv_key, protocol);
+
+    secure_stream.writes(&form.client_name())?;
+    secure_stream.flush().await?;
+
+    Ok(secure_stream
+        .reads::<bool>()
+        .await?
+        .then_some(secure_stream))
+}
+
+/// Performs a handshake with the client over the given transport and if successful, returns a
+/// secure stream.
+///
+/// Asynchronous counterpart of [perform_host_handshake](crate::handshake::perform_host_handshake).
+/// Must be called within a tokio runtime.
+pub async fn perform_host_handshake<T>(
+    mut stream: T,
+    mut form: Handshake,
+) -> io::Result<Result<SecureStream<T>, HostRejectionReason>>
+where
+    T: AsyncRead + AsyncWrite + Unpin,
+{
+    let exchange = exchange_hellos(&mut stream, &mut form, true).await?;
+    let client_proof: [u8; 32] = read_message(&mut stream).await?;
+
+    let (reply, result) = exchange.host_reply(&client_proof);
+    write_message(&mut stream, &reply).await?;
+
+    let protocol = match result {
+        Ok(protocol) => protocol,
+        Err(reason) => return Ok(Err(reason)),
+    };
+
+    let (send_key, recv_key) = exchange.host_keys();
+    let mut secure_stream = SecureStream::new(stream, send_key, recv_key, protocol);
+
+    let verdict = handshake::check_client_name(&form, secure_stream.reads().await)?;
+    secure_stream.writes(&verdict.is_ok())?;
+    secure_stream.flush().await?;
+
+    Ok(verdict.map(|_| secure_stream))
+}
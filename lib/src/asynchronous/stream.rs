@@ -0,0 +1,330 @@
+use std::collections::VecDeque;
+use std::io;
+use std::io::Cursor;
+use std::time::Duration;
+
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tokio::time;
+use tora::read::{FromReader, ToraRead};
+use tora::write::{SerializeIo, ToraWrite};
+
+use crate::handshake::Protocol;
+use crate::stream::{ChannelId, Fill, FrameCodec, StreamError, CONTROL_CHANNEL, LEN_PREFIX_LEN};
+
+/// The amount of times per heartbeat interval an idle read wakes up to send heartbeats and check
+/// for a dead peer.
+const TICKS_PER_HEARTBEAT: u32 = 4;
+
+/// An asynchronous, write-buffered stream encrypted with ChaCha20-Poly1305.
+///
+/// Speaks the same frame format as the blocking [SecureStream](crate::stream::SecureStream) and
+/// runs over any [AsyncRead] + [AsyncWrite] transport, [TcpStream] by default. Likewise, it carries
+/// several logical channels with their own flow control, of which [flush](Self::flush) and
+/// [reads](Self::reads) use the [CONTROL_CHANNEL].
+///
+/// If negotiated, idle reads send heartbeats and detect dead peers. A stream that is not read from
+/// for a while must call [keepalive](Self::keepalive) periodically instead.
+///
+/// Reads and flushes are not cancellation safe. A cancelled read or flush leaves the stream in an
+/// undefined state, so the stream should be dropped.
+pub struct SecureStream<T = TcpStream> {
+    stream: T,
+    codec: FrameCodec,
+    protocol: Protocol,
+    buf: Vec<u8>,
+    pending: VecDeque<(ChannelId, Vec<u8>)>,
+}
+
+impl<T> SecureStream<T>
+where
+    T: AsyncRead + AsyncWrite + Unpin,
+{
+    /// Instantiates a new SecureStream.
+    ///
+    /// # Parameters
+    ///
+    /// - stream: The underlying transport.
+    /// - send_key: The 256-bit key used to encrypt outgoing frames.
+    /// - recv_key: The 256-bit key used to decrypt incoming frames.
+    /// - protocol: The protocol negotiated during the handshake.
+    pub fn new(stream: T, send_key: [u8; 32], recv_key: [u8; 32], protocol: Protocol) -> Self {
+        Self {
+            stream,
+            codec: FrameCodec::new(send_key, recv_key, protocol),
+            protocol,
+            buf: Vec::new(),
+            pending: VecDeque::new(),
+        }
+    }
+
+    /// Returns a reference to the underlying transport.
+    pub const fn get_ref(&self) -> &T {
+        &self.stream
+    }
+
+    /// Returns the protocol negotiated with the peer.
+    pub const fn protocol(&self) -> Protocol {
+        self.protocol
+    }
+
+    /// Returns the maximum size of a received frame in bytes.
+    pub const fn max_frame_size(&self) -> u32 {
+        self.codec.max_frame_size()
+    }
+
+    /// Sets the maximum size of a received frame in bytes.
+    ///
+    /// Defaults to [DEFAULT_MAX_FRAME_SIZE](crate::stream::DEFAULT_MAX_FRAME_SIZE).
+    pub fn set_max_frame_size(&mut self, max_frame_size: u32) {
+        self.codec.set_max_frame_size(max_frame_size);
+    }
+
+    /// Returns the size in bytes from which outgoing frames are compressed.
+    ///
+    /// Returns None if the peer did not negotiate
+    /// [Features::COMPRESSION](crate::handshake::Features::COMPRESSION).
+    pub const fn compression_threshold(&self) -> Option<usize> {
+        self.codec.compression_threshold()
+    }
+
+    /// Sets the size in bytes from which outgoing frames are compressed.
+    ///
+    /// Defaults to [DEFAULT_COMPRESSION_THRESHOLD](crate::stream::DEFAULT_COMPRESSION_THRESHOLD).
+    /// Has no effect if compression was not negotiated.
+    pub fn set_compression_threshold(&mut self, threshold: usize) {
+        self.codec.set_compression_threshold(threshold);
+    }
+
+    /// Returns the amount of bytes sent under one key before the stream rekeys.
+    pub const fn rekey_after_bytes(&self) -> u64 {
+        self.codec.rekey_after_bytes()
+    }
+
+    /// Sets the amount of bytes sent under one key before the stream rekeys.
+    ///
+    /// Defaults to [DEFAULT_REKEY_AFTER_BYTES](crate::stream::DEFAULT_REKEY_AFTER_BYTES).
+    pub fn set_rekey_after_bytes(&mut self, bytes: u64) {
+        self.codec.set_rekey_after_bytes(bytes);
+    }
+
+    /// Returns the amount of time a key is used for before the stream rekeys.
+    pub const fn rekey_interval(&self) -> Duration {
+        self.codec.rekey_interval()
+    }
+
+    /// Sets the amount of time a key is used for before the stream rekeys.
+    ///
+    /// Defaults to [DEFAULT_REKEY_INTERVAL](crate::stream::DEFAULT_REKEY_INTERVAL). The budget is
+    /// checked whenever a frame is sent, so an idle stream rekeys with its next frame.
+    pub fn set_rekey_interval(&mut self, interval: Duration) {
+        self.codec.set_rekey_interval(interval);
+    }
+
+    /// Returns the amount of time without outgoing frames after which a heartbeat is sent.
+    ///
+    /// Returns None if the peer did not negotiate
+    /// [Features::HEARTBEAT](crate::handshake::Features::HEARTBEAT).
+    pub fn heartbeat_interval(&self) -> Option<Duration> {
+        self.codec.heartbeat_interval()
+    }
+
+    /// Sets the amount of time without outgoing frames after which a heartbeat is sent.
+    ///
+    /// Defaults to [DEFAULT_HEARTBEAT_INTERVAL](crate::stream::DEFAULT_HEARTBEAT_INTERVAL). The
+    /// interval is announced to the peer, which uses it to detect whether this side is dead. Has
+    /// no effect if heartbeats were not negotiated.
+    pub fn set_heartbeat_interval(&mut self, interval: Duration) {
+        self.codec.set_heartbeat_interval(interval);
+    }
+
+    /// Returns the amount of the peer's heartbeat intervals without incoming frames after which
+    /// the peer is considered dead.
+    pub const fn max_missed_heartbeats(&self) -> u32 {
+        self.codec.max_missed_heartbeats()
+    }
+
+    /// Sets the amount of the peer's heartbeat intervals without incoming frames after which the
+    /// peer is considered dead.
+    ///
+    /// Defaults to [DEFAULT_MAX_MISSED_HEARTBEATS](crate::stream::DEFAULT_MAX_MISSED_HEARTBEATS).
+    pub fn set_max_missed_heartbeats(&mut self, max_missed: u32) {
+        self.codec.set_max_missed_heartbeats(max_missed);
+    }
+
+    /// Serializes the given value into the write buffer.
+    ///
+    /// The buffer is sent as a single frame on the next [flush](Self::flush).
+    pub fn writes<S>(&mut self, s: &S) -> io::Result<()>
+    where
+        S: SerializeIo,
+    {
+        self.buf.writes(s)
+    }
+
+    /// Encrypts the write buffer and sends it as a single frame on the [CONTROL_CHANNEL].
+    pub async fn flush(&mut self) -> io::Result<()> {
+        self.flush_to(CONTROL_CHANNEL).await
+    }
+
+    /// Encrypts the write buffer and sends it as a single frame on the given channel.
+    ///
+    /// Waits until the peer read enough of the channel's window, keeping the frames that arrive
+    /// in the meantime for later reads.
+    pub async fn flush_to(&mut self, channel: ChannelId) -> io::Result<()> {
+        while !self.codec.can_send(channel) {
+            let Some(frame) = self.read_frame(false).await? else {
+                continue;
+            };
+            self.pending.extend(self.codec.open(&frame)?);
+        }
+        let frames = self.codec.seal(channel, &self.buf)?;
+
+        self.stream.write_all(&frames).await?;
+        self.stream.flush().await?;
+
+        self.buf.clear();
+        Ok(())
+    }
+
+    /// Reads the next data frame of the [CONTROL_CHANNEL] and deserializes `F` from it.
+    ///
+    /// Returns [io::ErrorKind::UnexpectedEof] if the transport was closed between frames, or
+    /// [StreamError::Truncated] if it was closed in the middle of one.
+    pub async fn reads<F>(&mut self) -> io::Result<F>
+    where
+        F: FromReader,
+    {
+        let (_, payload) = self.read_payload(Some(CONTROL_CHANNEL)).await?;
+
+        let mut reader = Cursor::new(payload);
+        reader.reads()
+    }
+
+    /// Reads the next data frame of any channel and deserializes `F` from it.
+    ///
+    /// Returns the channel the frame was sent on along with the value.
+    pub async fn reads_any<F>(&mut self) -> io::Result<(ChannelId, F)>
+    where
+        F: FromReader,
+    {
+        let (channel, payload) = self.read_payload(None).await?;
+
+        let mut reader = Cursor::new(payload);
+        Ok((channel, reader.reads()?))
+    }
+
+    /// Sends a heartbeat if one is due and processes the frames that already arrived.
+    ///
+    /// Returns [StreamError::PeerTimedOut] if the peer missed too many heartbeats. Data frames are
+    /// kept for the next read.
+    pub async fn keepalive(&mut self) -> io::Result<()> {
+        self.tick().await?;
+
+        while let Some(frame) = self.read_frame(true).await? {
+            self.pending.extend(self.codec.open(&frame)?);
+        }
+        Ok(())
+    }
+
+    /// Writes a heartbeat if one is due, or fails if the peer is considered dead.
+    async fn tick(&mut self) -> io::Result<()> {
+        if let Some(frame) = self.codec.tick()? {
+            self.stream.write_all(&frame).await?;
+            self.stream.flush().await?;
+        }
+        Ok(())
+    }
+
+    /// Fills the buffer from the transport, ticking whenever the transport was idle for a fraction
+    /// of the heartbeat interval.
+    ///
+    /// If `poll` is true, returns [Fill::Idle] if no byte is available right away. Returns
+    /// [StreamError::Truncated] if the transport reached its end after some bytes were read.
+    async fn fill(&mut self, buf: &mut [u8], poll: bool) -> io::Result<Fill> {
+        let mut filled = 0;
+
+        while filled < buf.len() {
+            let wait = match poll && filled == 0 {
+                true => Some(Duration::ZERO),
+                false => self
+                    .codec
+                    .heartbeat_interval()
+                    .map(|interval| interval / TICKS_PER_HEARTBEAT),
+            };
+            let read = self.stream.read(&mut buf[filled..]);
+
+            let read = match wait {
+                Some(wait) => time::timeout(wait, read).await.ok(),
+                None => Some(read.await),
+            };
+
+            match read {
+                Some(Ok(0)) if filled == 0 => return Ok(Fill::End),
+                Some(Ok(0)) => return Err(StreamError::Truncated.into()),
+                Some(Ok(n)) => filled += n,
+                Some(Err(err)) => return Err(err),
+                None => {
+                    self.tick().await?;
+
+                    if poll && filled == 0 {
+                        return Ok(Fill::Idle);
+                    }
+                }
+            }
+        }
+        Ok(Fill::Complete)
+    }
+
+    /// Reads a length-prefixed frame from the underlying transport.
+    ///
+    /// If `poll` is true, returns None if no frame is available right away.
+    async fn read_frame(&mut self, poll: bool) -> io::Result<Option<Vec<u8>>> {
+        let mut prefix = [0; LEN_PREFIX_LEN];
+
+        match self.fill(&mut prefix, poll).await? {
+            Fill::Complete => {}
+            Fill::End => return Err(io::ErrorKind::UnexpectedEof.into()),
+            Fill::Idle => return Ok(None),
+        }
+        let len = self.codec.check_len(u32::from_le_bytes(prefix))?;
+
+        let mut frame = vec![0; len];
+        match self.fill(&mut frame, false).await? {
+            Fill::Complete => Ok(Some(frame)),
+            _ => Err(StreamError::Truncated.into()),
+        }
+    }
+
+    /// Returns the payload of the next data frame on the given channel, or on any channel if
+    /// None, along with its channel, and grants it back to the peer.
+    ///
+    /// Payloads of other channels are kept for later reads.
+    async fn read_payload(
+        &mut self,
+        channel: Option<ChannelId>,
+    ) -> io::Result<(ChannelId, Vec<u8>)> {
+        let wanted = |id: ChannelId| channel.is_none_or(|channel| channel == id);
+
+        let (id, payload) = match self.pending.iter().position(|(id, _)| wanted(*id)) {
+            Some(index) => self.pending.remove(index).expect("index is in bounds"),
+            None => loop {
+                let Some(frame) = self.read_frame(false).await? else {
+                    continue;
+                };
+
+                match self.codec.open(&frame)? {
+                    Some((id, payload)) if wanted(id) => break (id, payload),
+                    Some(payload) => self.pending.push_back(payload),
+                    None => {}
+                }
+            },
+        };
+
+        if let Some(frame) = self.codec.consume(id, payload.len())? {
+            self.stream.write_all(&frame).await?;
+            self.stream.flush().await?;
+        }
+        Ok((id, payload))
+    }
+}
@@ -0,0 +1,81 @@
+use std::io;
+use std::io::{Read, Write};
+use std::mem;
+use std::ops::Deref;
+
+use tora::read::{FromReader, ToraRead};
+use tora::write::{SerializeIo, ToraWrite};
+
+/// The maximum amount of memory a decoded [BoundedVec] may reserve up front.
+pub const MAX_VEC_ALLOCATION: usize = 64 * 1024 * 1024;
+
+/// A [Vec] whose decoded length is checked before any memory is reserved.
+///
+/// Tora reserves capacity for the length prefix of a vector before reading its elements, so a
+/// forged length could make the reader allocate gigabytes. Strings need no such wrapper, since
+/// they are read byte by byte and cannot outgrow the frame they are decoded from.
+///
+/// Encoded exactly like a [Vec].
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct BoundedVec<T>(Vec<T>);
+
+impl<T> BoundedVec<T> {
+    /// Returns the inner vector.
+    pub fn into_inner(self) -> Vec<T> {
+        self.0
+    }
+}
+
+impl<T> Deref for BoundedVec<T> {
+    type Target = [T];
+
+    fn deref(&self) -> &[T] {
+        &self.0
+    }
+}
+
+impl<T> From<Vec<T>> for BoundedVec<T> {
+    fn from(vec: Vec<T>) -> Self {
+        Self(vec)
+    }
+}
+
+impl<T> FromReader for BoundedVec<T>
+where
+    T: FromReader,
+{
+    /// Reads a [u32] length and returns [io::ErrorKind::InvalidData] if the elements would take
+    /// more than [MAX_VEC_ALLOCATION] bytes, else reads the elements.
+    fn from_reader<R>(r: &mut R) -> io::Result<Self>
+    where
+        R: Read,
+    {
+        let len = r.reads::<u32>()? as usize;
+
+        if len.saturating_mul(mem::size_of::<T>().max(1)) > MAX_VEC_ALLOCATION {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("Decoded vector of {len} elements exceeds the allocation limit"),
+            ));
+        }
+
+        let mut buf = Vec::with_capacity(len);
+
+        for _ in 0..len {
+            buf.push(r.reads()?);
+        }
+        Ok(Self(buf))
+    }
+}
+
+impl<T> SerializeIo for BoundedVec<T>
+where
+    T: SerializeIo,
+{
+    fn serialize<W>(&self, w: &mut W) -> io::Result<()>
+    where
+        W: Write,
+    {
+        w.writes(&self.0)
+    }
+}
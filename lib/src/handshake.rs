@@ -1,43 +1,453 @@
 use std::io;
-use std::io::Write;
-use std::net::TcpStream;
+use std::io::{Read, Write};
 
+use chacha20poly1305::aead::rand_core::RngCore;
+use chacha20poly1305::aead::OsRng;
+use hkdf::Hkdf;
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
 use tora::read::ToraRead;
 use tora::write::ToraWrite;
 use tora::{ReadEnum, ReadStruct, WriteEnum, WriteStruct};
+use x25519_dalek::{EphemeralSecret, PublicKey};
 
-use crate::stream::SecureTcpStream;
+use crate::key::{CipherKey, SALT_LEN};
+use crate::stream::{SecureStream, StreamError};
+
+/// HKDF label of the key used for frames sent from the client to the host.
+const CLIENT_TO_HOST_LABEL: &[u8] = b"dori client to host";
+
+/// HKDF label of the key used for frames sent from the host to the client.
+const HOST_TO_CLIENT_LABEL: &[u8] = b"dori host to client";
+
+/// HMAC label of the client's proof of holding the cipher key.
+const CLIENT_PROOF_LABEL: &[u8] = b"dori client proof";
+
+/// HMAC label of the host's proof of holding the cipher key.
+const HOST_PROOF_LABEL: &[u8] = b"dori host proof";
+
+/// The protocol version spoken by this build.
+///
+/// Builds only speak their own version, so the handshake rejects any peer with a different one
+/// rather than downgrading. Must be incremented whenever anything on the wire changes: the
+/// handshake, the framing, the behaviour of a feature, or the encoding of
+/// [Operation](crate::operation::Operation) and [Response](crate::operation::Response). Only
+/// behaviour that an older peer can do without is added as one of the [Features] instead.
+pub const PROTOCOL_VERSION: u16 = 18;
+
+/// A set of optional protocol features.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, ReadStruct, WriteStruct)]
+pub struct Features(u32);
+
+impl Features {
+    /// Frames above a size threshold are compressed with zstd before encryption.
+    pub const COMPRESSION: Self = Self(1);
+    /// Idle streams exchange heartbeat frames, so that dead peers are detected.
+    pub const HEARTBEAT: Self = Self(2);
+    /// No optional features.
+    pub const NONE: Self = Self(0);
+    /// All features supported by this build.
+    pub const SUPPORTED: Self = Self::COMPRESSION.union(Self::HEARTBEAT);
+
+    /// Returns true if all features in `other` are enabled in this set.
+    pub const fn contains(self, other: Self) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    /// Returns the features enabled in either set.
+    pub const fn union(self, other: Self) -> Self {
+        Self(self.0 | other.0)
+    }
+
+    /// Returns the features enabled in both sets.
+    pub const fn intersection(self, other: Self) -> Self {
+        Self(self.0 & other.0)
+    }
+}
+
+/// The protocol version and features agreed on by the host and client.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Protocol {
+    version: u16,
+    features: Features,
+}
+
+impl Protocol {
+    /// Instantiates a new Protocol as if it was negotiated with the given version and features.
+    #[cfg(test)]
+    pub(crate) const fn new(version: u16, features: Features) -> Self {
+        Self { version, features }
+    }
+
+    /// Returns the negotiated protocol version.
+    pub const fn version(&self) -> u16 {
+        self.version
+    }
+
+    /// Returns the features enabled by both sides.
+    pub const fn features(&self) -> Features {
+        self.features
+    }
+
+    /// Negotiates the protocol spoken with a peer.
+    ///
+    /// Both sides enable the features they have in common. Returns None if the peer speaks a
+    /// different [PROTOCOL_VERSION].
+    fn negotiate(own: &Handshake, peer: &Hello) -> Option<Self> {
+        (own.protocol_version == peer.protocol_version).then_some(Self {
+            version: own.protocol_version,
+            features: own.features.intersection(peer.features),
+        })
+    }
+}
 
 /// A handshake between the host and client.
-#[derive(ReadStruct, WriteStruct)]
+#[derive(Clone, ReadStruct, WriteStruct)]
 pub struct Handshake {
     client_name: String,
-    key: String,
+    key: CipherKey,
+    protocol_version: u16,
+    features: Features,
 }
 
 impl Handshake {
-    pub const fn new(client_name: String, key: String) -> Self {
-        Self { client_name, key }
+    /// Instantiates a new Handshake offering the current protocol version and all supported
+    /// features.
+    ///
+    /// The host's passphrase must be [stretched](CipherKey::stretch) first, or the handshake fails.
+    /// The client's passphrase is stretched with the salt the host announces.
+    pub const fn new(client_name: String, key: CipherKey) -> Self {
+        Self {
+            client_name,
+            key,
+            protocol_version: PROTOCOL_VERSION,
+            features: Features::SUPPORTED,
+        }
+    }
+
+    /// Returns the name the client identifies itself with.
+    pub fn client_name(&self) -> &str {
+        &self.client_name
+    }
+
+    /// Returns the cipher key, which is stretched once the client learned the host's salt.
+    pub const fn key(&self) -> &CipherKey {
+        &self.key
+    }
+
+    /// Replaces the cipher key, usually with one stretched with the host's salt.
+    pub(crate) fn set_key(&mut self, key: CipherKey) {
+        self.key = key;
+    }
+
+    /// Restricts the features offered to the peer.
+    pub const fn with_features(mut self, features: Features) -> Self {
+        self.features = features.intersection(Features::SUPPORTED);
+        self
     }
 }
 
-#[derive(Debug, ReadEnum, WriteEnum)]
+#[derive(Clone, Copy, Debug, ReadEnum, WriteEnum)]
 pub enum HostRejectionReason {
     /// The client's name is incorrect.
     WrongClientName,
 
     /// The handshake could not be decrypted.
     DecryptionError,
+
+    /// The client could not prove that it holds the cipher key.
+    WrongKey,
+
+    /// The client speaks a different protocol version than the host.
+    ///
+    /// Contains the client's protocol version.
+    IncompatibleVersion(u16),
+}
+
+/// The host's answer to the client's proof.
+#[derive(ReadEnum, WriteEnum)]
+pub(crate) enum HostReply {
+    /// The client was authenticated. Contains the host's proof.
+    Accepted([u8; 32]),
+
+    /// The client was rejected.
+    Rejected(HostRejectionReason),
+}
+
+/// Session keys derived from an ephemeral key exchange.
+struct SessionKeys {
+    client_to_host: [u8; 32],
+    host_to_client: [u8; 32],
+}
+
+/// The first, unencrypted message sent by both sides of the handshake.
+///
+/// Carries the salt the sender's passphrase was stretched with, which is zero if the sender does
+/// not use a stretched passphrase. Since the message is part of the transcript, the salt is
+/// authenticated along with the rest of the handshake.
+#[derive(ReadStruct, WriteStruct)]
+pub(crate) struct Hello {
+    protocol_version: u16,
+    features: Features,
+    public_key: [u8; 32],
+    nonce: [u8; 32],
+    key_salt: [u8; SALT_LEN],
+}
+
+impl Hello {
+    /// Returns the salt the sender's passphrase was stretched with.
+    pub(crate) const fn key_salt(&self) -> [u8; SALT_LEN] {
+        self.key_salt
+    }
+
+    /// Serializes this message into the handshake transcript.
+    fn append_to(&self, transcript: &mut Vec<u8>) -> io::Result<()> {
+        transcript.writes(self)
+    }
+}
+
+/// This side's [Hello] along with the ephemeral secret it announces.
+pub(crate) struct PendingHello {
+    secret: EphemeralSecret,
+    hello: Hello,
+}
+
+impl PendingHello {
+    /// Generates an ephemeral X25519 secret and a random nonce.
+    pub(crate) fn new(form: &Handshake) -> Self {
+        let secret = EphemeralSecret::random_from_rng(OsRng);
+        let mut hello = Hello {
+            protocol_version: form.protocol_version,
+            features: form.features,
+            public_key: PublicKey::from(&secret).to_bytes(),
+            nonce: [0; 32],
+            key_salt: form.key.salt().unwrap_or_default(),
+        };
+        OsRng.fill_bytes(&mut hello.nonce);
+
+        Self { secret, hello }
+    }
+
+    /// Returns the message to be sent to the peer.
+    pub(crate) const fn hello(&self) -> &Hello {
+        &self.hello
+    }
+
+    /// Derives fresh session keys from the peer's [Hello].
+    ///
+    /// The pre-shared key is derived from the cipher key salted with both nonces, then mixed into
+    /// the derivation, so a peer without it ends up with different session keys. Since the
+    /// ephemeral secrets are discarded afterwards, recorded sessions cannot be decrypted with a
+    /// leaked pre-shared key.
+    pub(crate) fn complete(
+        self,
+        form: &Handshake,
+        peer_hello: Hello,
+        is_host: bool,
+    ) -> io::Result<Exchange> {
+        let shared_secret = self
+            .secret
+            .diffie_hellman(&PublicKey::from(peer_hello.public_key));
+
+        let (client_hello, host_hello) = match is_host {
+            true => (&peer_hello, &self.hello),
+            false => (&self.hello, &peer_hello),
+        };
+
+        let mut transcript = Vec::new();
+        client_hello.append_to(&mut transcript)?;
+        host_hello.append_to(&mut transcript)?;
+
+        let salt = [client_hello.nonce, host_hello.nonce].concat();
+        let psk = form.key.derive(&salt)?;
+        let hkdf = Hkdf::<Sha256>::new(Some(&psk), shared_secret.as_bytes());
+
+        let derive = |label: &[u8]| {
+            let mut okm = [0; 32];
+
+            hkdf.expand_multi_info(&[label, &transcript], &mut okm)
+                .map(|_| okm)
+                .map_err(|_| {
+                    io::Error::new(io::ErrorKind::InvalidInput, "Failed to derive session key")
+                })
+        };
+
+        let keys = SessionKeys {
+            client_to_host: derive(CLIENT_TO_HOST_LABEL)?,
+            host_to_client: derive(HOST_TO_CLIENT_LABEL)?,
+        };
+
+        Ok(Exchange {
+            psk,
+            transcript,
+            keys,
+            protocol: Protocol::negotiate(form, &peer_hello),
+            peer_version: peer_hello.protocol_version,
+        })
+    }
+}
+
+/// The result of exchanging [Hello] messages.
+pub(crate) struct Exchange {
+    psk: [u8; 32],
+    transcript: Vec<u8>,
+    keys: SessionKeys,
+    protocol: Option<Protocol>,
+    peer_version: u16,
 }
 
-/// Performs a handshake with the host and if successful, returns a secure TCP stream.
+impl Exchange {
+    /// Returns the client's proof of holding the pre-shared key.
+    pub(crate) fn client_proof(&self) -> [u8; 32] {
+        self.proof(CLIENT_PROOF_LABEL)
+    }
+
+    /// Checks the client's proof and protocol version, then returns the host's reply along with
+    /// the negotiated protocol if the client was accepted.
+    pub(crate) fn host_reply(
+        &self,
+        client_proof: &[u8; 32],
+    ) -> (HostReply, Result<Protocol, HostRejectionReason>) {
+        let result = match self.protocol {
+            None => Err(HostRejectionReason::IncompatibleVersion(self.peer_version)),
+            Some(_) if !self.verify(CLIENT_PROOF_LABEL, client_proof) => {
+                Err(HostRejectionReason::WrongKey)
+            }
+            Some(protocol) => Ok(protocol),
+        };
+
+        let reply = match result {
+            Ok(_) => HostReply::Accepted(self.proof(HOST_PROOF_LABEL)),
+            Err(reason) => HostReply::Rejected(reason),
+        };
+        (reply, result)
+    }
+
+    /// Checks the host's reply and returns the negotiated protocol.
+    ///
+    /// Returns None if the host rejected the client. Returns an error of kind
+    /// [io::ErrorKind::PermissionDenied] if the host could not prove that it holds the key, or
+    /// [io::ErrorKind::Unsupported] if the host speaks a different protocol version.
+    pub(crate) fn accept_host_reply(&self, reply: HostReply) -> io::Result<Option<Protocol>> {
+        let host_proof = match reply {
+            HostReply::Accepted(proof) => proof,
+            HostReply::Rejected(_) => return Ok(None),
+        };
+
+        if !self.verify(HOST_PROOF_LABEL, &host_proof) {
+            return Err(io::Error::new(
+                io::ErrorKind::PermissionDenied,
+                "Host failed to prove that it holds the cipher key",
+            ));
+        }
+
+        match self.protocol {
+            Some(protocol) => Ok(Some(protocol)),
+            None => Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "Host speaks a different protocol version",
+            )),
+        }
+    }
+
+    /// Returns the send and receive keys of the client.
+    pub(crate) const fn client_keys(&self) -> ([u8; 32], [u8; 32]) {
+        (self.keys.client_to_host, self.keys.host_to_client)
+    }
+
+    /// Returns the send and receive keys of the host.
+    pub(crate) const fn host_keys(&self) -> ([u8; 32], [u8; 32]) {
+        (self.keys.host_to_client, self.keys.client_to_host)
+    }
+
+    /// Computes this side's proof of holding the pre-shared key.
+    fn proof(&self, label: &[u8]) -> [u8; 32] {
+        self.mac(label).finalize().into_bytes().into()
+    }
+
+    /// Returns true if the peer's proof was computed with the same pre-shared key and transcript.
+    fn verify(&self, label: &[u8], proof: &[u8; 32]) -> bool {
+        self.mac(label).verify_slice(proof).is_ok()
+    }
+
+    fn mac(&self, label: &[u8]) -> Hmac<Sha256> {
+        let mut mac =
+            <Hmac<Sha256> as Mac>::new_from_slice(&self.psk).expect("HMAC accepts any key length");
+
+        mac.update(label);
+        mac.update(&self.transcript);
+        mac
+    }
+}
+
+/// Returns the host's verdict on the client's name, read from the first encrypted frame.
+///
+/// A frame that fails to decrypt is reported as [HostRejectionReason::DecryptionError].
+pub(crate) fn check_client_name(
+    form: &Handshake,
+    client_name: io::Result<String>,
+) -> io::Result<Result<(), HostRejectionReason>> {
+    match client_name {
+        Ok(name) if name == form.client_name => Ok(Ok(())),
+        Ok(_) => Ok(Err(HostRejectionReason::WrongClientName)),
+        Err(err) if StreamError::from_io(&err).is_some() => {
+            Ok(Err(HostRejectionReason::DecryptionError))
+        }
+        Err(err) => Err(err),
+    }
+}
+
+/// Exchanges [Hello] messages with the peer.
+///
+/// The client stretches its passphrase with the host's salt, unless it already did so in an
+/// earlier handshake.
+fn exchange_hellos<T>(stream: &mut T, form: &mut Handshake, is_host: bool) -> io::Result<Exchange>
+where
+    T: Read + Write,
+{
+    let pending = PendingHello::new(form);
+
+    stream.writes(pending.hello())?;
+    stream.flush()?;
+
+    let peer_hello: Hello = stream.reads()?;
+
+    if !is_host {
+        form.set_key(form.key.stretch(peer_hello.key_salt())?);
+    }
+    pending.complete(form, peer_hello, is_host)
+}
+
+/// Performs a handshake with the host over the given transport and if successful, returns a
+/// secure stream.
+///
+/// Both sides prove that they hold the cipher key by exchanging HMACs over the nonces, public keys
+/// and protocol versions of the handshake. Returns an error of kind
+/// [io::ErrorKind::PermissionDenied] if the host could not prove that it holds the key, or
+/// [io::ErrorKind::Unsupported] if the host speaks a different protocol version.
+///
+/// A passphrase is stretched with the salt the host announces. The stretched key is kept in the
+/// form, so that reconnecting with it does not stretch the passphrase again.
 ///
 /// Returns None if the host rejected the connection.
-pub fn perform_client_handshake(
-    stream: TcpStream,
-    form: Handshake,
-) -> io::Result<Option<SecureTcpStream>> {
-    let mut secure_stream = SecureTcpStream::new(stream, form.key);
+pub fn perform_client_handshake<T>(
+    mut stream: T,
+    form: &mut Handshake,
+) -> io::Result<Option<SecureStream<T>>>
+where
+    T: Read + Write,
+{
+    let exchange = exchange_hellos(&mut stream, form, false)?;
+
+    stream.writes(&exchange.client_proof())?;
+    stream.flush()?;
+
+    let Some(protocol) = exchange.accept_host_reply(stream.reads()?)? else {
+        return Ok(None);
+    };
+
+    let (send_key, recv_key) = exchange.client_keys();
+    let mut secure_stream = SecureStream::new(stream, send_key, recv_key, protocol);
 
     secure_stream.writes(&form.client_name)?;
     secure_stream.flush()?;
@@ -45,21 +455,135 @@ pub fn perform_client_handshake(
     Ok(secure_stream.reads::<bool>()?.then_some(secure_stream))
 }
 
-/// Performs a handshake with the client and if successful, returns a secure TCP stream.
-pub fn perform_host_handshake(
-    stream: TcpStream,
-    form: Handshake,
-) -> io::Result<Result<SecureTcpStream, HostRejectionReason>> {
-    let mut secure_stream = SecureTcpStream::new(stream, form.key);
+/// Performs a handshake with the client over the given transport and if successful, returns a
+/// secure stream.
+///
+/// Clients that speak a different protocol version are rejected. The client must prove that it
+/// holds the cipher key before its name is checked.
+pub fn perform_host_handshake<T>(
+    mut stream: T,
+    mut form: Handshake,
+) -> io::Result<Result<SecureStream<T>, HostRejectionReason>>
+where
+    T: Read + Write,
+{
+    let exchange = exchange_hellos(&mut stream, &mut form, true)?;
+    let client_proof: [u8; 32] = stream.reads()?;
 
-    let client_name: String = secure_stream.reads()?;
+    let (reply, result) = exchange.host_reply(&client_proof);
+    stream.writes(&reply)?;
+    stream.flush()?;
 
-    if client_name != form.client_name {
-        secure_stream.writes(&false)?;
-        secure_stream.flush()?;
-        return Ok(Err(HostRejectionReason::WrongClientName));
-    }
-    secure_stream.writes(&true)?;
+    let protocol = match result {
+        Ok(protocol) => protocol,
+        Err(reason) => return Ok(Err(reason)),
+    };
+
+    let (send_key, recv_key) = exchange.host_keys();
+    let mut secure_stream = SecureStream::new(stream, send_key, recv_key, protocol);
+
+    let verdict = check_client_name(&form, secure_stream.reads())?;
+    secure_stream.writes(&verdict.is_ok())?;
     secure_stream.flush()?;
-    Ok(Ok(secure_stream))
+
+    Ok(verdict.map(|_| secure_stream))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use super::*;
+    use crate::key;
+    use crate::stream::tests::{pipe, Pipe};
+
+    /// The outcome of a handshake on the host's and the client's side.
+    type Outcome = (
+        Result<SecureStream<Pipe>, HostRejectionReason>,
+        Option<SecureStream<Pipe>>,
+    );
+
+    /// Performs a handshake between the host and the client over an in-memory pipe.
+    fn handshake(host: Handshake, client: &mut Handshake) -> io::Result<Outcome> {
+        let (host_end, client_end) = pipe();
+        let host = thread::spawn(move || perform_host_handshake(host_end, host));
+
+        let client = perform_client_handshake(client_end, client)?;
+        let host = host.join().expect("host panicked")?;
+        Ok((host, client))
+    }
+
+    /// Returns a handshake form for the client named "client" with the given key material.
+    fn form(key: &[u8]) -> Handshake {
+        Handshake::new("client".to_string(), CipherKey::Bytes(key.to_vec()))
+    }
+
+    #[test]
+    fn handshake_carries_data_both_ways() -> io::Result<()> {
+        let (Ok(mut host), Some(mut client)) = handshake(form(&[1; 32]), &mut form(&[1; 32]))?
+        else {
+            panic!("handshake failed");
+        };
+        assert_eq!(host.protocol(), client.protocol());
+        assert_eq!(host.protocol().version(), PROTOCOL_VERSION);
+
+        client.writes(&"request".to_string())?;
+        client.flush()?;
+        assert_eq!(host.reads::<String>()?, "request");
+
+        host.writes(&"response".to_string())?;
+        host.flush()?;
+        assert_eq!(client.reads::<String>()?, "response");
+        Ok(())
+    }
+
+    #[test]
+    fn client_stretches_passphrase_with_host_salt() -> io::Result<()> {
+        let passphrase = CipherKey::Passphrase("secret".to_string());
+        let salt = key::generate_salt();
+        let host = Handshake::new("client".to_string(), passphrase.stretch(salt)?);
+        let mut client = Handshake::new("client".to_string(), passphrase);
+
+        let (host, client_stream) = handshake(host, &mut client)?;
+
+        assert!(host.is_ok() && client_stream.is_some());
+        assert_eq!(client.key().salt(), Some(salt));
+        Ok(())
+    }
+
+    #[test]
+    fn wrong_key_is_rejected() -> io::Result<()> {
+        let (host, client) = handshake(form(&[1; 32]), &mut form(&[2; 32]))?;
+
+        assert!(matches!(host, Err(HostRejectionReason::WrongKey)));
+        assert!(client.is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn wrong_client_name_is_rejected() -> io::Result<()> {
+        let mut client = form(&[1; 32]);
+        client.client_name = "intruder".to_string();
+
+        let (host, client) = handshake(form(&[1; 32]), &mut client)?;
+
+        assert!(matches!(host, Err(HostRejectionReason::WrongClientName)));
+        assert!(client.is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn incompatible_version_is_rejected() -> io::Result<()> {
+        let mut client = form(&[1; 32]);
+        client.protocol_version = PROTOCOL_VERSION - 1;
+
+        let (host, client) = handshake(form(&[1; 32]), &mut client)?;
+
+        assert!(matches!(
+            host,
+            Err(HostRejectionReason::IncompatibleVersion(version)) if version == PROTOCOL_VERSION - 1
+        ));
+        assert!(client.is_none());
+        Ok(())
+    }
 }
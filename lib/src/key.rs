@@ -0,0 +1,112 @@
+use std::path::Path;
+use std::{fs, io};
+
+use argon2::Argon2;
+use chacha20poly1305::aead::rand_core::RngCore;
+use chacha20poly1305::aead::OsRng;
+use hkdf::Hkdf;
+use sha2::Sha256;
+use tora::{ReadEnum, WriteEnum};
+
+/// The minimum amount of bytes a key file must contain.
+pub const MIN_KEY_FILE_LEN: usize = 32;
+
+/// HKDF label of the pre-shared key derived from binary key material.
+const PSK_LABEL: &[u8] = b"dori pre-shared key";
+
+/// The length of the random salt passphrases are stretched with.
+pub const SALT_LEN: usize = 16;
+
+/// Generates a random salt to stretch a passphrase with.
+pub fn generate_salt() -> [u8; SALT_LEN] {
+    let mut salt = [0; SALT_LEN];
+
+    OsRng.fill_bytes(&mut salt);
+    salt
+}
+
+/// The secret shared by the host and client.
+#[derive(Clone, ReadEnum, WriteEnum)]
+pub enum CipherKey {
+    /// A human-readable passphrase, which must be [stretched](CipherKey::stretch) before it is
+    /// used by the host.
+    Passphrase(String),
+
+    /// Uniformly random key material, usually loaded from a key file.
+    Bytes(Vec<u8>),
+
+    /// A passphrase stretched with Argon2id.
+    ///
+    /// Contains the passphrase, the salt and the resulting key material.
+    Stretched(String, [u8; SALT_LEN], Vec<u8>),
+}
+
+impl CipherKey {
+    /// Reads binary key material from the file at the given path.
+    ///
+    /// Returns [io::ErrorKind::InvalidData] if the file contains less than [MIN_KEY_FILE_LEN]
+    /// bytes.
+    pub fn from_file<P>(path: P) -> io::Result<Self>
+    where
+        P: AsRef<Path>,
+    {
+        let bytes = fs::read(path)?;
+
+        if bytes.len() < MIN_KEY_FILE_LEN {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("Key file must contain at least {MIN_KEY_FILE_LEN} bytes"),
+            ));
+        }
+        Ok(Self::Bytes(bytes))
+    }
+
+    /// Stretches a passphrase with Argon2id and the given salt into binary key material.
+    ///
+    /// Stretching is slow on purpose, so the host stretches its passphrase once with a random
+    /// salt, which it announces in every handshake. A passphrase that was already stretched with
+    /// the same salt and binary key material are returned as is.
+    pub fn stretch(&self, salt: [u8; SALT_LEN]) -> io::Result<Self> {
+        let passphrase = match self {
+            Self::Passphrase(passphrase) => passphrase,
+            Self::Stretched(passphrase, current, _) if *current != salt => passphrase,
+            _ => return Ok(self.clone()),
+        };
+        let mut bytes = vec![0; 32];
+
+        Argon2::default()
+            .hash_password_into(passphrase.as_bytes(), &salt, &mut bytes)
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err.to_string()))?;
+
+        Ok(Self::Stretched(passphrase.clone(), salt, bytes))
+    }
+
+    /// Returns the salt the passphrase was stretched with.
+    ///
+    /// Returns None if this is not a stretched passphrase.
+    pub const fn salt(&self) -> Option<[u8; SALT_LEN]> {
+        match self {
+            Self::Stretched(_, salt, _) => Some(*salt),
+            _ => None,
+        }
+    }
+
+    /// Derives the 256-bit pre-shared key with the given salt, using HKDF-SHA256.
+    ///
+    /// Returns [io::ErrorKind::InvalidInput] if this is a passphrase that was not stretched.
+    pub(crate) fn derive(&self, salt: &[u8]) -> io::Result<[u8; 32]> {
+        let (Self::Bytes(bytes) | Self::Stretched(_, _, bytes)) = self else {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "Passphrase must be stretched before the handshake",
+            ));
+        };
+        let mut psk = [0; 32];
+
+        Hkdf::<Sha256>::new(Some(salt), bytes)
+            .expand(PSK_LABEL, &mut psk)
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "Failed to expand key"))?;
+
+        Ok(psk)
+    }
+}
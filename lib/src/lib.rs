@@ -1,3 +1,16 @@
+/// Asynchronous counterparts of the secure stream, handshake and connections, built on tokio.
+///
+/// Requires the `async` feature.
+#[cfg(feature = "async")]
+pub mod asynchronous;
+pub mod bounded;
 pub mod handshake;
+pub mod key;
 pub mod operation;
 pub mod stream;
+/// TLS transport with certificate pinning, built on rustls.
+///
+/// Requires the `tls` feature.
+#[cfg(feature = "tls")]
+pub mod tls;
+pub mod transfer;
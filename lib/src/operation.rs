@@ -1,30 +1,369 @@
+use std::path::Path;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use std::{fmt, fs, io};
+
 use tora::{ReadEnum, ReadStruct, WriteEnum, WriteStruct};
 
+use crate::bounded::BoundedVec;
+use crate::transfer;
+
 /// A result returned by the client.
-pub type ClientResult<T> = Result<T, String>;
+pub type ClientResult<T> = Result<T, ClientError>;
+
+/// An error returned by the client.
+#[derive(Clone, Debug, ReadEnum, WriteEnum)]
+pub enum ClientError {
+    /// An I/O operation failed.
+    Io(IoError),
+
+    /// The operation failed for a reason other than I/O.
+    Other(String),
+}
+
+impl ClientError {
+    /// Instantiates a new [Io](Self::Io) error for an operation on the given path.
+    pub fn io<P>(err: &io::Error, path: P) -> Self
+    where
+        P: AsRef<Path>,
+    {
+        let mut err = IoError::from(err);
+
+        err.path = Some(path.as_ref().display().to_string());
+        Self::Io(err)
+    }
+
+    /// Returns the kind of I/O error, if this is an I/O error.
+    pub const fn io_kind(&self) -> Option<IoErrorKind> {
+        match self {
+            Self::Io(err) => Some(err.kind),
+            Self::Other(_) => None,
+        }
+    }
+}
+
+impl fmt::Display for ClientError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io(err) => write!(f, "{err}"),
+            Self::Other(msg) => f.write_str(msg),
+        }
+    }
+}
+
+impl From<io::Error> for ClientError {
+    fn from(err: io::Error) -> Self {
+        Self::Io(IoError::from(&err))
+    }
+}
+
+/// An I/O error that occurred on the client.
+#[derive(Clone, Debug, ReadStruct, WriteStruct)]
+pub struct IoError {
+    kind: IoErrorKind,
+    os_code: Option<i32>,
+    path: Option<String>,
+    message: String,
+}
+
+impl IoError {
+    /// Returns the kind of this error.
+    pub const fn kind(&self) -> IoErrorKind {
+        self.kind
+    }
+
+    /// Returns the raw error code of the client's operating system, if any.
+    ///
+    /// The code is only meaningful on the client's platform.
+    pub const fn os_code(&self) -> Option<i32> {
+        self.os_code
+    }
+
+    /// Returns the client-side path involved, if any.
+    pub fn path(&self) -> Option<&str> {
+        self.path.as_deref()
+    }
+
+    /// Returns the error message.
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+}
+
+impl fmt::Display for IoError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.path {
+            Some(path) => write!(f, "{path}: {}", self.message),
+            None => f.write_str(&self.message),
+        }
+    }
+}
+
+impl From<&io::Error> for IoError {
+    fn from(err: &io::Error) -> Self {
+        Self {
+            kind: err.kind().into(),
+            os_code: err.raw_os_error(),
+            path: None,
+            message: err.to_string(),
+        }
+    }
+}
+
+/// The kind of an [IoError].
+///
+/// Mirrors the variants of [io::ErrorKind] the host can react to, since those are not encodable.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, ReadEnum, WriteEnum)]
+pub enum IoErrorKind {
+    /// The path does not exist.
+    NotFound,
+
+    /// The client lacks the permissions for the operation.
+    PermissionDenied,
+
+    /// The path already exists.
+    AlreadyExists,
+
+    /// The path is not a directory, but a directory was expected.
+    NotADirectory,
+
+    /// The path is a directory, but a file was expected.
+    IsADirectory,
+
+    /// The directory is not empty.
+    DirectoryNotEmpty,
+
+    /// The filesystem is read-only.
+    ReadOnlyFilesystem,
+
+    /// The storage is full.
+    StorageFull,
+
+    /// An argument of the operation was invalid.
+    InvalidInput,
+
+    /// The data involved in the operation was invalid.
+    InvalidData,
+
+    /// Any other kind of error.
+    Other,
+}
+
+impl From<io::ErrorKind> for IoErrorKind {
+    fn from(kind: io::ErrorKind) -> Self {
+        match kind {
+            io::ErrorKind::NotFound => Self::NotFound,
+            io::ErrorKind::PermissionDenied => Self::PermissionDenied,
+            io::ErrorKind::AlreadyExists => Self::AlreadyExists,
+            io::ErrorKind::NotADirectory => Self::NotADirectory,
+            io::ErrorKind::IsADirectory => Self::IsADirectory,
+            io::ErrorKind::DirectoryNotEmpty => Self::DirectoryNotEmpty,
+            io::ErrorKind::ReadOnlyFilesystem => Self::ReadOnlyFilesystem,
+            io::ErrorKind::StorageFull => Self::StorageFull,
+            io::ErrorKind::InvalidInput => Self::InvalidInput,
+            io::ErrorKind::InvalidData => Self::InvalidData,
+            _ => Self::Other,
+        }
+    }
+}
+
+impl From<IoErrorKind> for io::ErrorKind {
+    fn from(kind: IoErrorKind) -> Self {
+        match kind {
+            IoErrorKind::NotFound => Self::NotFound,
+            IoErrorKind::PermissionDenied => Self::PermissionDenied,
+            IoErrorKind::AlreadyExists => Self::AlreadyExists,
+            IoErrorKind::NotADirectory => Self::NotADirectory,
+            IoErrorKind::IsADirectory => Self::IsADirectory,
+            IoErrorKind::DirectoryNotEmpty => Self::DirectoryNotEmpty,
+            IoErrorKind::ReadOnlyFilesystem => Self::ReadOnlyFilesystem,
+            IoErrorKind::StorageFull => Self::StorageFull,
+            IoErrorKind::InvalidInput => Self::InvalidInput,
+            IoErrorKind::InvalidData => Self::InvalidData,
+            IoErrorKind::Other => Self::Other,
+        }
+    }
+}
+
+/// The ID of a [Request], which is echoed in its [Reply].
+pub type RequestId = u64;
+
+/// An [Operation] tagged with the ID that its [Reply] carries.
+///
+/// IDs let the host pipeline several operations and match replies that arrive out of order.
+#[derive(ReadStruct, WriteStruct)]
+pub struct Request {
+    id: RequestId,
+    operation: Operation,
+}
+
+impl Request {
+    /// Instantiates a new Request.
+    pub const fn new(id: RequestId, operation: Operation) -> Self {
+        Self { id, operation }
+    }
+
+    /// Returns the ID of this request.
+    pub const fn id(&self) -> RequestId {
+        self.id
+    }
+
+    /// Returns the requested operation.
+    pub const fn operation(&self) -> &Operation {
+        &self.operation
+    }
+
+    /// Returns the requested operation, discarding the ID.
+    pub fn into_operation(self) -> Operation {
+        self.operation
+    }
+}
+
+/// A [Response] tagged with the ID of the [Request] it answers.
+#[derive(ReadStruct, WriteStruct)]
+pub struct Reply {
+    id: RequestId,
+    response: Response,
+}
+
+impl Reply {
+    /// Instantiates a new Reply to the request with the given ID.
+    pub const fn new(id: RequestId, response: Response) -> Self {
+        Self { id, response }
+    }
+
+    /// Returns the ID of the request this reply answers.
+    pub const fn id(&self) -> RequestId {
+        self.id
+    }
+
+    /// Returns the response.
+    pub const fn response(&self) -> &Response {
+        &self.response
+    }
+
+    /// Returns the response, discarding the ID.
+    pub fn into_response(self) -> Response {
+        self.response
+    }
+}
 
 /// An operation that is sent by the host and executed on the client.
 ///
 /// # Supported Operations
 ///
-/// - Upload: uploads a file to the client
-/// - Download: downloads a file from the client
+/// - BeginUpload, UploadChunk, FinishUpload: upload a file to the client in chunks
+/// - Download, DownloadChunk: download a file from the client in chunks
+/// - ListTree: lists a directory tree on the client, so that it can be downloaded
 /// - Command: executes a shell command and awaits the completion and output as a response
 /// - ThreadedCommand: spawns a thread and executes the Command operation
+/// - CreateDirectory: creates a directory and all of its missing parents on the client
+/// - ListDirectory, Stat, Remove, Rename, Copy: inspect and manage the client's file system
+/// - Hash: computes the digests of files on the client
+/// - SystemInfo: returns an inventory of the client's system
+/// - ListProcesses, Kill: inspect and terminate processes on the client
+/// - OpenShell, ShellInput, ResizeShell, CloseShell: run an interactive shell session on the client
 /// - Ping: an empty operation used to measure the send and response time of the connection
 #[derive(ReadEnum, WriteEnum)]
 pub enum Operation {
-    /// Uploads a file to the client.
-    Upload(FileTransferOperation),
+    /// Starts uploading a file of the given size to the given path, or resumes an interrupted
+    /// upload to it.
+    ///
+    /// The file is received at its [partial path](crate::transfer::partial_path) until the upload
+    /// is finished.
+    BeginUpload(String, u64),
 
-    /// Downloads a file from the client.
-    Download(FileTransferOperation),
+    /// Writes a chunk of a begun upload.
+    ///
+    /// Chunks must be sent in order, but several may be in flight at once.
+    UploadChunk(FileChunk),
 
-    /// Executes a shell command and awaits the completion and output as a response.
-    Command(String, Vec<String>),
+    /// Verifies a fully uploaded file against the given SHA-256 digest and moves it to its path.
+    ///
+    /// The partially received file is deleted if it does not match. The Unix mode is applied to
+    /// the file if one is given and the client supports it.
+    FinishUpload(String, BoundedVec<u8>, Option<u32>),
+
+    /// Starts downloading the file at the given path.
+    ///
+    /// The client replies with the file's metadata, including the digest the downloaded file is
+    /// verified with.
+    Download(String),
+
+    /// Reads the chunk of the file at the given path that starts at the given offset.
+    ///
+    /// Chunks may be requested in any order, and several may be in flight at once.
+    DownloadChunk(String, u64),
+
+    /// Lists the directory at the given path and all of its descendants.
+    ///
+    /// Used to download directory trees.
+    ListTree(String),
+
+    /// Executes the program with the given arguments and awaits the completion and output as a
+    /// response.
+    ///
+    /// The program is killed if it runs past the timeout of the limits, and only as much output
+    /// as they allow is returned.
+    Command(String, BoundedVec<String>, CommandLimits),
 
     /// Spawns a thread and executes the [Command](Self::Command) operation.
-    ThreadedCommand(Vec<String>),
+    ThreadedCommand(BoundedVec<String>),
+
+    /// Creates a directory and all of its missing parents on the client.
+    CreateDirectory(String),
+
+    /// Lists the entries of the directory at the given path.
+    ListDirectory(String),
+
+    /// Returns the status of the entry at the given path, without following symbolic links.
+    Stat(String),
+
+    /// Removes the file or directory at the given path.
+    ///
+    /// A directory that is not empty is only removed if the flag is set, along with its contents.
+    Remove(String, bool),
+
+    /// Moves the entry at the first path to the second path, replacing a file there.
+    Rename(String, String),
+
+    /// Copies the file at the first path to the second path, replacing a file there.
+    ///
+    /// Directories are only copied if the flag is set, along with their contents.
+    Copy(String, String, bool),
+
+    /// Computes the digests of the files at the given paths with the given algorithm.
+    Hash(BoundedVec<String>, HashAlgorithm),
+
+    /// Returns an inventory of the client's operating system, hardware and disks.
+    SystemInfo,
+
+    /// Lists the processes running on the client.
+    ListProcesses,
+
+    /// Terminates the processes the target matches with the given signal.
+    ///
+    /// Without a signal, processes are terminated the platform's default way, which is SIGTERM
+    /// on Unix. Other platforms only support SIGKILL, which is also their default.
+    Kill(ProcessTarget, Option<Signal>),
+
+    /// Starts an interactive shell session on the client.
+    ///
+    /// The session is identified by the ID of this request. After the
+    /// [OpenShell](Response::OpenShell) response, the client streams the shell's output as
+    /// [ShellOutput](Response::ShellOutput) replies and ends the session with a
+    /// [ShellExit](Response::ShellExit) reply, all carrying the same ID.
+    OpenShell(ShellOptions),
+
+    /// Writes the data to the input of the shell session with the given ID.
+    ///
+    /// Not replied to, like the other operations on a running session.
+    ShellInput(RequestId, BoundedVec<u8>),
+
+    /// Resizes the terminal of the shell session with the given ID to the given columns and rows.
+    ResizeShell(RequestId, u16, u16),
+
+    /// Terminates the shell session with the given ID.
+    CloseShell(RequestId),
 
     /// An empty operation used to measure the send and response time of the host-client connection.
     Ping,
@@ -33,41 +372,888 @@ pub enum Operation {
 /// A response to an [Operation].
 #[derive(ReadEnum, WriteEnum)]
 pub enum Response {
-    /// The response to the upload operation.
-    Upload(ClientResult<()>),
+    /// The response to the begin upload operation, carrying the offset to continue the upload at.
+    BeginUpload(ClientResult<u64>),
+
+    /// The response to the upload chunk operation, carrying the amount of bytes received so far.
+    UploadChunk(ClientResult<u64>),
+
+    /// The response to the finish upload operation.
+    FinishUpload(ClientResult<()>),
+
+    /// The response to the download operation, carrying the metadata of the file.
+    Download(ClientResult<FileMetadata>),
+
+    /// The response to the download chunk operation, carrying the content of the chunk.
+    ///
+    /// The chunk is empty if the offset is at or past the end of the file.
+    DownloadChunk(ClientResult<BoundedVec<u8>>),
+
+    /// The response to the list tree operation, carrying the entries below the directory.
+    ListTree(ClientResult<BoundedVec<TreeEntry>>),
+
+    /// The response to the command operation.
+    Command(ClientResult<CommandOutput>),
+
+    /// The response to the create directory operation.
+    CreateDirectory(ClientResult<()>),
+
+    /// The response to the list directory operation, carrying the entries of the directory.
+    ListDirectory(ClientResult<BoundedVec<DirEntry>>),
 
-    /// The response to the download operation.
-    Download(ClientResult<()>),
+    /// The response to the stat operation.
+    Stat(ClientResult<FileStat>),
+
+    /// The response to the remove operation.
+    Remove(ClientResult<()>),
+
+    /// The response to the rename operation.
+    Rename(ClientResult<()>),
+
+    /// The response to the copy operation, carrying the amount of bytes copied.
+    Copy(ClientResult<u64>),
+
+    /// The response to the hash operation, carrying the digest of each path in the same order.
+    Hash(BoundedVec<ClientResult<BoundedVec<u8>>>),
+
+    /// The response to the system info operation.
+    SystemInfo(SystemInfo),
+
+    /// The response to the list processes operation, carrying the processes sorted by PID.
+    ListProcesses(BoundedVec<ProcessInfo>),
+
+    /// The response to the kill operation, carrying the PIDs of the processes signaled.
+    Kill(ClientResult<BoundedVec<u32>>),
+
+    /// The response to the open shell operation.
+    OpenShell(ClientResult<()>),
+
+    /// Output of a shell session.
+    ShellOutput(BoundedVec<u8>),
+
+    /// The end of a shell session, carrying the shell's exit code.
+    ///
+    /// The exit code is None if the shell was terminated by a signal or could not be waited on.
+    ShellExit(Option<i32>),
 
     /// The response to the ping operation.
     Pong,
 }
 
-/// Operation in which a file is transferred to or from the client.
+/// The metadata of a file on the client.
 #[derive(WriteStruct, ReadStruct)]
-pub struct FileTransferOperation {
+pub struct FileMetadata {
+    size: u64,
+    modified: Option<u64>,
+    readonly: bool,
+    mode: Option<u32>,
+    digest: BoundedVec<u8>,
+}
+
+impl FileMetadata {
+    /// Returns the size of the file in bytes.
+    pub const fn size(&self) -> u64 {
+        self.size
+    }
+
+    /// Returns the time the file was last modified at, or None if the platform does not record
+    /// it.
+    pub fn modified(&self) -> Option<SystemTime> {
+        from_unix_secs(self.modified)
+    }
+
+    /// Returns true if the file is read-only.
+    pub const fn readonly(&self) -> bool {
+        self.readonly
+    }
+
+    /// Returns the Unix mode of the file, or None if the client does not run on Unix.
+    pub const fn mode(&self) -> Option<u32> {
+        self.mode
+    }
+
+    /// Returns the SHA-256 digest of the file's content.
+    pub fn digest(&self) -> &[u8] {
+        &self.digest
+    }
+
+    /// Instantiates a new FileMetadata.
+    ///
+    /// # Parameters
+    ///
+    /// - size: The size of the file in bytes.
+    /// - modified: The time the file was last modified at, if known.
+    /// - readonly: Whether the file is read-only.
+    /// - mode: The Unix mode of the file, if the client runs on Unix.
+    /// - digest: The SHA-256 digest of the file's content.
+    pub fn new(
+        size: u64,
+        modified: Option<SystemTime>,
+        readonly: bool,
+        mode: Option<u32>,
+        digest: Vec<u8>,
+    ) -> Self {
+        Self {
+            size,
+            modified: to_unix_secs(modified),
+            readonly,
+            mode,
+            digest: digest.into(),
+        }
+    }
+}
+
+/// A file or directory below the root of a directory tree.
+#[derive(Clone, Debug, WriteStruct, ReadStruct)]
+pub struct TreeEntry {
     path: String,
-    content: Vec<u8>,
+    directory: bool,
+    size: u64,
 }
 
-impl FileTransferOperation {
-    /// Returns the path to the file destination.
+impl TreeEntry {
+    /// Returns the path of the entry relative to the root, with components separated by `/`.
     pub fn path(&self) -> &str {
         &self.path
     }
 
-    /// Returns the file content as a slice.
-    pub fn content(&self) -> &[u8] {
-        &self.content
+    /// Returns true if the entry is a directory.
+    pub const fn is_dir(&self) -> bool {
+        self.directory
+    }
+
+    /// Returns the size of the file in bytes, or zero for directories.
+    pub const fn size(&self) -> u64 {
+        self.size
+    }
+
+    /// Instantiates a new TreeEntry.
+    ///
+    /// # Parameters
+    ///
+    /// - path: The path of the entry relative to the root, with components separated by `/`.
+    /// - directory: Whether the entry is a directory.
+    /// - size: The size of the file in bytes, or zero for directories.
+    pub fn new(path: String, directory: bool, size: u64) -> Self {
+        Self {
+            path,
+            directory,
+            size,
+        }
+    }
+}
+
+/// The type of an entry in the client's file system.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, ReadEnum, WriteEnum)]
+pub enum FileType {
+    /// A regular file.
+    File,
+
+    /// A directory.
+    Directory,
+
+    /// A symbolic link.
+    Symlink,
+
+    /// Any other entry, like a socket or a device.
+    Other,
+}
+
+impl From<fs::FileType> for FileType {
+    fn from(file_type: fs::FileType) -> Self {
+        if file_type.is_symlink() {
+            Self::Symlink
+        } else if file_type.is_dir() {
+            Self::Directory
+        } else if file_type.is_file() {
+            Self::File
+        } else {
+            Self::Other
+        }
+    }
+}
+
+/// The status of an entry in the client's file system.
+///
+/// Symbolic links are described themselves rather than what they point to.
+#[derive(Clone, Debug, WriteStruct, ReadStruct)]
+pub struct FileStat {
+    file_type: FileType,
+    size: u64,
+    modified: Option<u64>,
+    accessed: Option<u64>,
+    created: Option<u64>,
+    readonly: bool,
+    mode: Option<u32>,
+    link_target: Option<String>,
+}
+
+impl FileStat {
+    /// Returns the type of the entry.
+    pub const fn file_type(&self) -> FileType {
+        self.file_type
+    }
+
+    /// Returns the size of the entry in bytes.
+    pub const fn size(&self) -> u64 {
+        self.size
+    }
+
+    /// Returns the time the entry was last modified at, or None if the platform does not record
+    /// it.
+    pub fn modified(&self) -> Option<SystemTime> {
+        from_unix_secs(self.modified)
+    }
+
+    /// Returns the time the entry was last accessed at, or None if the platform does not record
+    /// it.
+    pub fn accessed(&self) -> Option<SystemTime> {
+        from_unix_secs(self.accessed)
+    }
+
+    /// Returns the time the entry was created at, or None if the platform does not record it.
+    pub fn created(&self) -> Option<SystemTime> {
+        from_unix_secs(self.created)
     }
 
-    /// Instantiates a new FileTransferOperation.
+    /// Returns true if the entry is read-only.
+    pub const fn readonly(&self) -> bool {
+        self.readonly
+    }
+
+    /// Returns the Unix mode of the entry, or None if the client does not run on Unix.
+    pub const fn mode(&self) -> Option<u32> {
+        self.mode
+    }
+
+    /// Returns the path a symbolic link points to, or None if the entry is not a symbolic link.
+    pub fn link_target(&self) -> Option<&str> {
+        self.link_target.as_deref()
+    }
+
+    /// Instantiates a new FileStat from the metadata of an entry, which must not follow symbolic
+    /// links.
     ///
     /// # Parameters
     ///
-    /// - path: The path to the file destination.
-    /// - content: The file content.
-    pub const fn new(path: String, content: Vec<u8>) -> Self {
-        Self { path, content }
+    /// - metadata: The metadata of the entry.
+    /// - link_target: The path the entry points to, if it is a symbolic link.
+    pub fn new(metadata: &fs::Metadata, link_target: Option<String>) -> Self {
+        Self {
+            file_type: metadata.file_type().into(),
+            size: metadata.len(),
+            modified: to_unix_secs(metadata.modified().ok()),
+            accessed: to_unix_secs(metadata.accessed().ok()),
+            created: to_unix_secs(metadata.created().ok()),
+            readonly: metadata.permissions().readonly(),
+            mode: transfer::file_mode(metadata),
+            link_target,
+        }
+    }
+}
+
+/// An entry of a directory on the client.
+#[derive(Clone, Debug, WriteStruct, ReadStruct)]
+pub struct DirEntry {
+    name: String,
+    stat: FileStat,
+}
+
+impl DirEntry {
+    /// Returns the file name of the entry.
+    pub fn name(&self) -> &str {
+        &self.name
     }
+
+    /// Returns the status of the entry.
+    pub const fn stat(&self) -> &FileStat {
+        &self.stat
+    }
+
+    /// Instantiates a new DirEntry.
+    ///
+    /// # Parameters
+    ///
+    /// - name: The file name of the entry.
+    /// - stat: The status of the entry.
+    pub fn new(name: String, stat: FileStat) -> Self {
+        Self { name, stat }
+    }
+}
+
+/// An algorithm files can be hashed with.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, ReadEnum, WriteEnum)]
+pub enum HashAlgorithm {
+    /// SHA-256, which transferred files are verified with.
+    Sha256,
+
+    /// BLAKE3, which is considerably faster on large files.
+    Blake3,
+}
+
+impl fmt::Display for HashAlgorithm {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Sha256 => write!(f, "SHA-256"),
+            Self::Blake3 => write!(f, "BLAKE3"),
+        }
+    }
+}
+
+/// An inventory of the client's system.
+#[derive(Clone, Debug, WriteStruct, ReadStruct)]
+pub struct SystemInfo {
+    os: OsInfo,
+    cpu_count: u32,
+    total_memory: u64,
+    free_memory: u64,
+    disks: BoundedVec<DiskInfo>,
+    uptime_secs: u64,
+    client_version: String,
+}
+
+impl SystemInfo {
+    /// Returns the operating system of the client.
+    pub const fn os(&self) -> &OsInfo {
+        &self.os
+    }
+
+    /// Returns the amount of logical CPUs.
+    pub const fn cpu_count(&self) -> u32 {
+        self.cpu_count
+    }
+
+    /// Returns the total amount of memory in bytes.
+    pub const fn total_memory(&self) -> u64 {
+        self.total_memory
+    }
+
+    /// Returns the amount of memory available to new processes in bytes.
+    pub const fn free_memory(&self) -> u64 {
+        self.free_memory
+    }
+
+    /// Returns the mounted disk volumes.
+    pub fn disks(&self) -> &[DiskInfo] {
+        &self.disks
+    }
+
+    /// Returns how long the system has been running.
+    pub const fn uptime(&self) -> Duration {
+        Duration::from_secs(self.uptime_secs)
+    }
+
+    /// Returns the version of the client binary.
+    pub fn client_version(&self) -> &str {
+        &self.client_version
+    }
+
+    /// Instantiates a new SystemInfo.
+    ///
+    /// # Parameters
+    ///
+    /// - os: The operating system of the client.
+    /// - cpu_count: The amount of logical CPUs.
+    /// - total_memory: The total amount of memory in bytes.
+    /// - free_memory: The amount of memory available to new processes in bytes.
+    /// - disks: The mounted disk volumes.
+    /// - uptime: How long the system has been running.
+    /// - client_version: The version of the client binary.
+    pub fn new(
+        os: OsInfo,
+        cpu_count: u32,
+        total_memory: u64,
+        free_memory: u64,
+        disks: Vec<DiskInfo>,
+        uptime: Duration,
+        client_version: String,
+    ) -> Self {
+        Self {
+            os,
+            cpu_count,
+            total_memory,
+            free_memory,
+            disks: disks.into(),
+            uptime_secs: uptime.as_secs(),
+            client_version,
+        }
+    }
+}
+
+/// The operating system of the client.
+#[derive(Clone, Debug, WriteStruct, ReadStruct)]
+pub struct OsInfo {
+    name: Option<String>,
+    version: Option<String>,
+    kernel_version: Option<String>,
+    hostname: Option<String>,
+    arch: String,
+}
+
+impl OsInfo {
+    /// Returns the name of the operating system, if known.
+    pub fn name(&self) -> Option<&str> {
+        self.name.as_deref()
+    }
+
+    /// Returns the version of the operating system, if known.
+    pub fn version(&self) -> Option<&str> {
+        self.version.as_deref()
+    }
+
+    /// Returns the version of the kernel, if known.
+    pub fn kernel_version(&self) -> Option<&str> {
+        self.kernel_version.as_deref()
+    }
+
+    /// Returns the hostname of the client, if known.
+    pub fn hostname(&self) -> Option<&str> {
+        self.hostname.as_deref()
+    }
+
+    /// Returns the CPU architecture, like `x86_64`.
+    pub fn arch(&self) -> &str {
+        &self.arch
+    }
+
+    /// Instantiates a new OsInfo.
+    ///
+    /// # Parameters
+    ///
+    /// - name: The name of the operating system, if known.
+    /// - version: The version of the operating system, if known.
+    /// - kernel_version: The version of the kernel, if known.
+    /// - hostname: The hostname of the client, if known.
+    /// - arch: The CPU architecture.
+    pub const fn new(
+        name: Option<String>,
+        version: Option<String>,
+        kernel_version: Option<String>,
+        hostname: Option<String>,
+        arch: String,
+    ) -> Self {
+        Self {
+            name,
+            version,
+            kernel_version,
+            hostname,
+            arch,
+        }
+    }
+}
+
+/// A mounted disk volume of the client.
+#[derive(Clone, Debug, WriteStruct, ReadStruct)]
+pub struct DiskInfo {
+    name: String,
+    mount_point: String,
+    file_system: String,
+    total_space: u64,
+    available_space: u64,
+}
+
+impl DiskInfo {
+    /// Returns the name of the device.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Returns the path the volume is mounted at.
+    pub fn mount_point(&self) -> &str {
+        &self.mount_point
+    }
+
+    /// Returns the name of the file system, like `ext4` or `NTFS`.
+    pub fn file_system(&self) -> &str {
+        &self.file_system
+    }
+
+    /// Returns the size of the volume in bytes.
+    pub const fn total_space(&self) -> u64 {
+        self.total_space
+    }
+
+    /// Returns the amount of space available to the client in bytes.
+    pub const fn available_space(&self) -> u64 {
+        self.available_space
+    }
+
+    /// Instantiates a new DiskInfo.
+    ///
+    /// # Parameters
+    ///
+    /// - name: The name of the device.
+    /// - mount_point: The path the volume is mounted at.
+    /// - file_system: The name of the file system.
+    /// - total_space: The size of the volume in bytes.
+    /// - available_space: The amount of space available to the client in bytes.
+    pub const fn new(
+        name: String,
+        mount_point: String,
+        file_system: String,
+        total_space: u64,
+        available_space: u64,
+    ) -> Self {
+        Self {
+            name,
+            mount_point,
+            file_system,
+            total_space,
+            available_space,
+        }
+    }
+}
+
+/// A process running on the client.
+#[derive(Clone, Debug, WriteStruct, ReadStruct)]
+pub struct ProcessInfo {
+    pid: u32,
+    parent_pid: Option<u32>,
+    name: String,
+    command: BoundedVec<String>,
+    cpu_usage: f32,
+    memory: u64,
+}
+
+impl ProcessInfo {
+    /// Returns the ID of the process.
+    pub const fn pid(&self) -> u32 {
+        self.pid
+    }
+
+    /// Returns the ID of the parent process, if any.
+    pub const fn parent_pid(&self) -> Option<u32> {
+        self.parent_pid
+    }
+
+    /// Returns the name of the process.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Returns the command line of the process, which is empty if it cannot be read.
+    pub fn command(&self) -> &[String] {
+        &self.command
+    }
+
+    /// Returns the CPU usage of the process in percent of a single CPU.
+    pub const fn cpu_usage(&self) -> f32 {
+        self.cpu_usage
+    }
+
+    /// Returns the resident memory of the process in bytes.
+    pub const fn memory(&self) -> u64 {
+        self.memory
+    }
+
+    /// Instantiates a new ProcessInfo.
+    ///
+    /// # Parameters
+    ///
+    /// - pid: The ID of the process.
+    /// - parent_pid: The ID of the parent process, if any.
+    /// - name: The name of the process.
+    /// - command: The command line of the process.
+    /// - cpu_usage: The CPU usage of the process in percent of a single CPU.
+    /// - memory: The resident memory of the process in bytes.
+    pub fn new(
+        pid: u32,
+        parent_pid: Option<u32>,
+        name: String,
+        command: Vec<String>,
+        cpu_usage: f32,
+        memory: u64,
+    ) -> Self {
+        Self {
+            pid,
+            parent_pid,
+            name,
+            command: command.into(),
+            cpu_usage,
+            memory,
+        }
+    }
+}
+
+/// The processes a kill operation applies to.
+#[derive(Clone, Debug, Eq, PartialEq, ReadEnum, WriteEnum)]
+pub enum ProcessTarget {
+    /// The process with the given ID.
+    Pid(u32),
+
+    /// All processes with exactly the given name.
+    Name(String),
+}
+
+impl fmt::Display for ProcessTarget {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Pid(pid) => write!(f, "PID {pid}"),
+            Self::Name(name) => write!(f, "{name}"),
+        }
+    }
+}
+
+/// A Unix signal processes can be terminated with.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, ReadEnum, WriteEnum)]
+pub enum Signal {
+    /// SIGHUP, the hangup of the controlling terminal.
+    Hangup,
+
+    /// SIGINT, an interrupt from the keyboard.
+    Interrupt,
+
+    /// SIGQUIT, a quit from the keyboard.
+    Quit,
+
+    /// SIGKILL, which cannot be caught or ignored.
+    Kill,
+
+    /// SIGTERM, a request to terminate.
+    Terminate,
+
+    /// SIGUSR1, the first user-defined signal.
+    User1,
+
+    /// SIGUSR2, the second user-defined signal.
+    User2,
+
+    /// SIGSTOP, which stops the process.
+    Stop,
+
+    /// SIGCONT, which continues a stopped process.
+    Continue,
+}
+
+impl Signal {
+    /// All signals.
+    pub const ALL: [Self; 9] = [
+        Self::Hangup,
+        Self::Interrupt,
+        Self::Quit,
+        Self::Kill,
+        Self::User1,
+        Self::User2,
+        Self::Terminate,
+        Self::Continue,
+        Self::Stop,
+    ];
+
+    /// Returns the name of the signal without the SIG prefix, like `TERM`.
+    pub const fn name(&self) -> &'static str {
+        match self {
+            Self::Hangup => "HUP",
+            Self::Interrupt => "INT",
+            Self::Quit => "QUIT",
+            Self::Kill => "KILL",
+            Self::Terminate => "TERM",
+            Self::User1 => "USR1",
+            Self::User2 => "USR2",
+            Self::Stop => "STOP",
+            Self::Continue => "CONT",
+        }
+    }
+}
+
+impl fmt::Display for Signal {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "SIG{}", self.name())
+    }
+}
+
+/// A chunk of a file that is being transferred.
+#[derive(WriteStruct, ReadStruct)]
+pub struct FileChunk {
+    path: String,
+    offset: u64,
+    data: BoundedVec<u8>,
+}
+
+impl FileChunk {
+    /// Returns the path of the file on the client.
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    /// Returns the offset of the chunk within the file.
+    pub const fn offset(&self) -> u64 {
+        self.offset
+    }
+
+    /// Returns the content of the chunk.
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+
+    /// Instantiates a new FileChunk.
+    ///
+    /// # Parameters
+    ///
+    /// - path: The path of the file on the client.
+    /// - offset: The offset of the chunk within the file.
+    /// - data: The content of the chunk.
+    pub fn new(path: String, offset: u64, data: Vec<u8>) -> Self {
+        Self {
+            path,
+            offset,
+            data: data.into(),
+        }
+    }
+}
+
+/// The limits of a command executed on the client.
+#[derive(Clone, Copy, Debug, WriteStruct, ReadStruct)]
+pub struct CommandLimits {
+    max_output: u32,
+    timeout_secs: u64,
+}
+
+impl CommandLimits {
+    /// Returns the maximum amount of bytes of each output stream that is returned.
+    pub const fn max_output(&self) -> u32 {
+        self.max_output
+    }
+
+    /// Returns how long the command may run for before it is killed.
+    pub const fn timeout(&self) -> Duration {
+        Duration::from_secs(self.timeout_secs)
+    }
+
+    /// Instantiates a new CommandLimits.
+    ///
+    /// # Parameters
+    ///
+    /// - max_output: The maximum amount of bytes of each output stream that is returned.
+    /// - timeout: How long the command may run for before it is killed.
+    pub const fn new(max_output: u32, timeout: Duration) -> Self {
+        Self {
+            max_output,
+            timeout_secs: timeout.as_secs(),
+        }
+    }
+}
+
+/// The output of a command executed on the client.
+#[derive(WriteStruct, ReadStruct)]
+pub struct CommandOutput {
+    stdout: BoundedVec<u8>,
+    stderr: BoundedVec<u8>,
+    truncated: bool,
+    exit_code: Option<i32>,
+    timed_out: bool,
+    duration_millis: u64,
+}
+
+impl CommandOutput {
+    /// Returns the captured standard output.
+    pub fn stdout(&self) -> &[u8] {
+        &self.stdout
+    }
+
+    /// Returns the captured standard error.
+    pub fn stderr(&self) -> &[u8] {
+        &self.stderr
+    }
+
+    /// Returns true if either output stream exceeded the limit and was cut off.
+    pub const fn truncated(&self) -> bool {
+        self.truncated
+    }
+
+    /// Returns the exit code of the command.
+    ///
+    /// Returns None if the command was terminated by a signal, which includes being killed after
+    /// a timeout.
+    pub const fn exit_code(&self) -> Option<i32> {
+        self.exit_code
+    }
+
+    /// Returns true if the command was killed because it ran past the timeout.
+    pub const fn timed_out(&self) -> bool {
+        self.timed_out
+    }
+
+    /// Returns how long the command ran for.
+    pub const fn duration(&self) -> Duration {
+        Duration::from_millis(self.duration_millis)
+    }
+
+    /// Instantiates a new CommandOutput.
+    ///
+    /// # Parameters
+    ///
+    /// - stdout: The captured standard output.
+    /// - stderr: The captured standard error.
+    /// - truncated: Whether either output stream was cut off.
+    /// - exit_code: The exit code, if the command exited normally.
+    /// - timed_out: Whether the command was killed after a timeout.
+    /// - duration: How long the command ran for.
+    pub fn new(
+        stdout: Vec<u8>,
+        stderr: Vec<u8>,
+        truncated: bool,
+        exit_code: Option<i32>,
+        timed_out: bool,
+        duration: Duration,
+    ) -> Self {
+        Self {
+            stdout: stdout.into(),
+            stderr: stderr.into(),
+            truncated,
+            exit_code,
+            timed_out,
+            duration_millis: duration.as_millis().try_into().unwrap_or(u64::MAX),
+        }
+    }
+}
+
+/// The options of an interactive shell session.
+#[derive(WriteStruct, ReadStruct)]
+pub struct ShellOptions {
+    program: Option<String>,
+    cols: u16,
+    rows: u16,
+}
+
+impl ShellOptions {
+    /// Returns the program to run, or None for the client's default shell.
+    pub fn program(&self) -> Option<&str> {
+        self.program.as_deref()
+    }
+
+    /// Returns the initial amount of columns of the terminal.
+    pub const fn cols(&self) -> u16 {
+        self.cols
+    }
+
+    /// Returns the initial amount of rows of the terminal.
+    pub const fn rows(&self) -> u16 {
+        self.rows
+    }
+
+    /// Instantiates new ShellOptions.
+    ///
+    /// # Parameters
+    ///
+    /// - program: The program to run, or None for the client's default shell.
+    /// - cols: The initial amount of columns of the terminal.
+    /// - rows: The initial amount of rows of the terminal.
+    pub const fn new(program: Option<String>, cols: u16, rows: u16) -> Self {
+        Self {
+            program,
+            cols,
+            rows,
+        }
+    }
+}
+
+/// Converts the time to seconds since the Unix epoch, as times are encoded.
+fn to_unix_secs(time: Option<SystemTime>) -> Option<u64> {
+    time.and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+        .map(|since| since.as_secs())
+}
+
+/// Converts seconds since the Unix epoch back to a time.
+fn from_unix_secs(secs: Option<u64>) -> Option<SystemTime> {
+    secs.map(|secs| UNIX_EPOCH + Duration::from_secs(secs))
 }
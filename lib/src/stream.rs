@@ -1,55 +1,1316 @@
+use std::collections::VecDeque;
+use std::error::Error;
+use std::fmt::{Display, Formatter};
 use std::io;
-use std::io::{Cursor, Write};
+use std::io::{Cursor, Read, Write};
 use std::net::TcpStream;
+use std::time::{Duration, Instant};
 
-use magic_crypt::{MagicCrypt256, MagicCryptTrait};
+use chacha20poly1305::aead::{Aead, AeadCore, OsRng, Payload};
+use chacha20poly1305::{ChaCha20Poly1305, Key, KeyInit, Nonce};
+use hkdf::Hkdf;
+use sha2::Sha256;
 use tora::read::{FromReader, ToraRead};
-use tora::write::ToraWrite;
 
-/// A write-buffered 256-bit encrypted [TcpStream].
-pub struct SecureTcpStream {
-    stream: TcpStream,
-    crypt: MagicCrypt256,
+use crate::handshake::{Features, Protocol};
+
+/// The length of the little-endian length prefix of every frame.
+pub(crate) const LEN_PREFIX_LEN: usize = 4;
+
+/// The length of the sequence number prepended to every frame.
+const SEQ_LEN: usize = 8;
+
+/// The length of the nonce following the sequence number of every frame.
+const NONCE_LEN: usize = 12;
+
+/// The length of the authentication tag appended to every frame.
+const TAG_LEN: usize = 16;
+
+/// The length of the frame header preceding the payload inside the ciphertext, made of the
+/// header flags and the little-endian channel ID.
+const HEADER_LEN: usize = 3;
+
+/// The amount of bytes a frame adds to its payload.
+const FRAME_OVERHEAD: usize = SEQ_LEN + NONCE_LEN + TAG_LEN + HEADER_LEN;
+
+/// The default size in bytes from which outgoing frames are compressed.
+pub const DEFAULT_COMPRESSION_THRESHOLD: usize = 512;
+
+/// The zstd compression level of outgoing frames.
+const COMPRESSION_LEVEL: i32 = 3;
+
+/// Header flag marking a compressed frame.
+const FLAG_COMPRESSED: u8 = 1;
+
+/// Header flag marking a rekey frame, after which the sender switches to its next key.
+const FLAG_REKEY: u8 = 2;
+
+/// Header flag marking a heartbeat frame, which carries the sender's heartbeat interval.
+const FLAG_HEARTBEAT: u8 = 4;
+
+/// Header flag marking a credit frame, which grants the sender more of a channel's window.
+const FLAG_CREDIT: u8 = 8;
+
+/// Identifies one of the logical channels multiplexed over a secure stream.
+pub type ChannelId = u16;
+
+/// The channel used by reads and writes that do not name one.
+pub const CONTROL_CHANNEL: ChannelId = 0;
+
+/// The amount of channels a secure stream carries, numbered from [CONTROL_CHANNEL] up.
+pub const CHANNELS: ChannelId = 4;
+
+/// The amount of bytes sent on a channel that the receiver has not read yet, after which the
+/// sender waits for the receiver to grant more.
+///
+/// Both sides must use the same window, so it is part of the protocol.
+pub const CHANNEL_WINDOW: u64 = 8 * 1024 * 1024;
+
+/// HKDF label of the traffic key derived from the previous one when rekeying.
+const REKEY_LABEL: &[u8] = b"dori rekey";
+
+/// The default amount of bytes sent under one key before rekeying.
+pub const DEFAULT_REKEY_AFTER_BYTES: u64 = 1024 * 1024 * 1024;
+
+/// The default amount of time a key is used for before rekeying.
+pub const DEFAULT_REKEY_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// The default amount of time without outgoing frames after which a heartbeat is sent.
+pub const DEFAULT_HEARTBEAT_INTERVAL: Duration = Duration::from_secs(10);
+
+/// The default amount of consecutive heartbeat intervals without incoming frames after which the
+/// peer is considered dead.
+pub const DEFAULT_MAX_MISSED_HEARTBEATS: u32 = 3;
+
+/// The default maximum size of a received frame in bytes.
+pub const DEFAULT_MAX_FRAME_SIZE: u32 = 64 * 1024 * 1024 + FRAME_OVERHEAD as u32;
+
+/// An error returned when a received frame cannot be accepted.
+///
+/// Returned wrapped in an [io::Error] of kind [io::ErrorKind::InvalidData], or
+/// [io::ErrorKind::TimedOut] for [StreamError::PeerTimedOut].
+#[derive(Debug, Eq, PartialEq)]
+pub enum StreamError {
+    /// The frame is too short to contain a sequence number, nonce and authentication tag.
+    MalformedFrame,
+
+    /// The frame failed authentication and may have been tampered with.
+    TamperedFrame,
+
+    /// The frame's length prefix exceeds the maximum frame size.
+    ///
+    /// Contains the announced frame length.
+    FrameTooLarge(u32),
+
+    /// The stream was closed after rejecting an oversized frame or losing its peer.
+    Closed,
+
+    /// The frame's sequence number was already received.
+    ///
+    /// Contains the sequence number of the frame.
+    ReplayedFrame(u64),
+
+    /// The frame's sequence number skips ahead of the expected one, so frames were dropped or
+    /// reordered.
+    OutOfOrderFrame {
+        /// The sequence number of the next expected frame.
+        expected: u64,
+
+        /// The sequence number of the received frame.
+        received: u64,
+    },
+
+    /// The transport was closed in the middle of a frame.
+    Truncated,
+
+    /// The peer missed too many heartbeats and is considered dead.
+    PeerTimedOut,
+
+    /// The frame belongs to a channel the stream does not carry.
+    ///
+    /// Contains the channel ID of the frame.
+    UnknownChannel(ChannelId),
+
+    /// The peer sent more than the window of a channel, or granted more than was sent on it.
+    ///
+    /// Contains the channel ID of the frame.
+    WindowExceeded(ChannelId),
+}
+
+impl StreamError {
+    /// Returns the stream error wrapped by the given I/O error, if any.
+    pub fn from_io(err: &io::Error) -> Option<&Self> {
+        err.get_ref()?.downcast_ref()
+    }
+}
+
+impl Display for StreamError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::MalformedFrame => write!(f, "Malformed frame"),
+            Self::TamperedFrame => write!(f, "Frame failed authentication"),
+            Self::FrameTooLarge(len) => write!(f, "Frame of {len} bytes exceeds the size limit"),
+            Self::Closed => write!(f, "Stream was closed"),
+            Self::ReplayedFrame(seq) => write!(f, "Frame {seq} was replayed"),
+            Self::OutOfOrderFrame { expected, received } => {
+                write!(f, "Expected frame {expected} but received frame {received}")
+            }
+            Self::Truncated => write!(f, "Stream was truncated in the middle of a frame"),
+            Self::PeerTimedOut => write!(f, "Peer missed too many heartbeats"),
+            Self::UnknownChannel(channel) => write!(f, "Frame on unknown channel {channel}"),
+            Self::WindowExceeded(channel) => {
+                write!(f, "Peer violated the flow control of channel {channel}")
+            }
+        }
+    }
+}
+
+impl Error for StreamError {}
+
+impl From<StreamError> for io::Error {
+    fn from(err: StreamError) -> Self {
+        let kind = match err {
+            StreamError::PeerTimedOut => io::ErrorKind::TimedOut,
+            _ => io::ErrorKind::InvalidData,
+        };
+        io::Error::new(kind, err)
+    }
+}
+
+/// The flow control state of a single channel.
+#[derive(Clone, Copy, Default)]
+struct Window {
+    /// The amount of bytes sent on the channel that the peer did not grant back yet.
+    unacked: u64,
+
+    /// The amount of bytes received on the channel that were not granted back yet.
+    outstanding: u64,
+
+    /// The part of the outstanding bytes that was already read.
+    consumed: u64,
+}
+
+/// Encrypts outgoing and decrypts incoming frames.
+///
+/// Holds the frame layer state shared by the blocking [SecureStream] and its asynchronous
+/// counterpart, independent of how the frames are transported.
+pub(crate) struct FrameCodec {
+    send_key: [u8; 32],
+    recv_key: [u8; 32],
+    send_cipher: ChaCha20Poly1305,
+    recv_cipher: ChaCha20Poly1305,
+    send_seq: u64,
+    recv_seq: u64,
+    rekey_after_bytes: u64,
+    rekey_interval: Duration,
+    sent_since_rekey: u64,
+    last_rekey: Instant,
+    heartbeats: bool,
+    heartbeat_interval: Duration,
+    max_missed_heartbeats: u32,
+    peer_heartbeat_interval: Option<Duration>,
+    interval_announced: bool,
+    last_sent: Option<Instant>,
+    last_received: Instant,
+    compression_threshold: Option<usize>,
+    max_frame_size: u32,
+    windows: [Window; CHANNELS as usize],
+    closed: bool,
+}
+
+impl FrameCodec {
+    /// Instantiates a new FrameCodec with the keys of both directions.
+    ///
+    /// Frames are compressed above [DEFAULT_COMPRESSION_THRESHOLD] if both sides negotiated
+    /// [Features::COMPRESSION], and heartbeats are exchanged if both sides negotiated
+    /// [Features::HEARTBEAT].
+    pub(crate) fn new(send_key: [u8; 32], recv_key: [u8; 32], protocol: Protocol) -> Self {
+        let compression = protocol.features().contains(Features::COMPRESSION);
+
+        Self {
+            send_key,
+            recv_key,
+            send_cipher: ChaCha20Poly1305::new(Key::from_slice(&send_key)),
+            recv_cipher: ChaCha20Poly1305::new(Key::from_slice(&recv_key)),
+            send_seq: 0,
+            recv_seq: 0,
+            rekey_after_bytes: DEFAULT_REKEY_AFTER_BYTES,
+            rekey_interval: DEFAULT_REKEY_INTERVAL,
+            sent_since_rekey: 0,
+            last_rekey: Instant::now(),
+            heartbeats: protocol.features().contains(Features::HEARTBEAT),
+            heartbeat_interval: DEFAULT_HEARTBEAT_INTERVAL,
+            max_missed_heartbeats: DEFAULT_MAX_MISSED_HEARTBEATS,
+            peer_heartbeat_interval: None,
+            interval_announced: false,
+            last_sent: None,
+            last_received: Instant::now(),
+            compression_threshold: compression.then_some(DEFAULT_COMPRESSION_THRESHOLD),
+            max_frame_size: DEFAULT_MAX_FRAME_SIZE,
+            windows: [Window::default(); CHANNELS as usize],
+            closed: false,
+        }
+    }
+
+    /// Returns the size in bytes from which outgoing frames are compressed.
+    ///
+    /// Returns None if compression was not negotiated.
+    pub(crate) const fn compression_threshold(&self) -> Option<usize> {
+        self.compression_threshold
+    }
+
+    /// Sets the size in bytes from which outgoing frames are compressed.
+    ///
+    /// Has no effect if compression was not negotiated.
+    pub(crate) fn set_compression_threshold(&mut self, threshold: usize) {
+        if let Some(current) = self.compression_threshold.as_mut() {
+            *current = threshold;
+        }
+    }
+
+    /// Returns the maximum size of a received frame in bytes.
+    pub(crate) const fn max_frame_size(&self) -> u32 {
+        self.max_frame_size
+    }
+
+    /// Sets the maximum size of a received frame in bytes.
+    pub(crate) fn set_max_frame_size(&mut self, max_frame_size: u32) {
+        self.max_frame_size = max_frame_size;
+    }
+
+    /// Returns the amount of bytes sent under one key before rekeying.
+    pub(crate) const fn rekey_after_bytes(&self) -> u64 {
+        self.rekey_after_bytes
+    }
+
+    /// Sets the amount of bytes sent under one key before rekeying.
+    pub(crate) fn set_rekey_after_bytes(&mut self, bytes: u64) {
+        self.rekey_after_bytes = bytes;
+    }
+
+    /// Returns the amount of time a key is used for before rekeying.
+    pub(crate) const fn rekey_interval(&self) -> Duration {
+        self.rekey_interval
+    }
+
+    /// Sets the amount of time a key is used for before rekeying.
+    pub(crate) fn set_rekey_interval(&mut self, interval: Duration) {
+        self.rekey_interval = interval;
+    }
+
+    /// Returns the amount of time without outgoing frames after which a heartbeat is sent.
+    ///
+    /// Returns None if heartbeats were not negotiated.
+    pub(crate) fn heartbeat_interval(&self) -> Option<Duration> {
+        self.heartbeats.then_some(self.heartbeat_interval)
+    }
+
+    /// Sets the amount of time without outgoing frames after which a heartbeat is sent.
+    pub(crate) fn set_heartbeat_interval(&mut self, interval: Duration) {
+        self.heartbeat_interval = interval;
+        self.interval_announced = false;
+    }
+
+    /// Returns the amount of heartbeat intervals without incoming frames after which the peer is
+    /// considered dead.
+    pub(crate) const fn max_missed_heartbeats(&self) -> u32 {
+        self.max_missed_heartbeats
+    }
+
+    /// Sets the amount of heartbeat intervals without incoming frames after which the peer is
+    /// considered dead.
+    pub(crate) fn set_max_missed_heartbeats(&mut self, max_missed: u32) {
+        self.max_missed_heartbeats = max_missed;
+    }
+
+    /// Returns [StreamError::Closed] if this codec rejected an oversized frame or lost its peer.
+    pub(crate) fn ensure_open(&self) -> io::Result<()> {
+        match self.closed {
+            true => Err(StreamError::Closed.into()),
+            false => Ok(()),
+        }
+    }
+
+    /// Checks the length prefix of an incoming frame before the frame is allocated.
+    ///
+    /// If it exceeds the maximum frame size, [StreamError::FrameTooLarge] is returned and the codec
+    /// refuses any further frames, so that the transport is closed once the stream is dropped.
+    pub(crate) fn check_len(&mut self, len: u32) -> io::Result<usize> {
+        self.ensure_open()?;
+
+        if len > self.max_frame_size {
+            self.closed = true;
+            return Err(StreamError::FrameTooLarge(len).into());
+        }
+        Ok(len as usize)
+    }
+
+    /// Returns false while the peer has not granted back enough of the channel's window to send
+    /// another frame on it.
+    pub(crate) fn can_send(&self, channel: ChannelId) -> bool {
+        self.windows
+            .get(channel as usize)
+            .is_none_or(|window| window.unacked < CHANNEL_WINDOW)
+    }
+
+    /// Records that the application read a payload of the given length from the channel.
+    ///
+    /// Returns a credit frame to be written to the transport once half of the channel's window
+    /// was read, which grants the read bytes back to the peer.
+    pub(crate) fn consume(
+        &mut self,
+        channel: ChannelId,
+        len: usize,
+    ) -> io::Result<Option<Vec<u8>>> {
+        let window = &mut self.windows[channel as usize];
+
+        window.consumed += len as u64;
+
+        if window.consumed < CHANNEL_WINDOW / 2 {
+            return Ok(None);
+        }
+        let credit = window.consumed;
+
+        window.outstanding -= credit;
+        window.consumed = 0;
+
+        let payload = [
+            &header(FLAG_CREDIT, channel),
+            credit.to_le_bytes().as_slice(),
+        ]
+        .concat();
+        let mut frame = Vec::new();
+
+        self.seal_frame(&payload, &mut frame)?;
+        Ok(Some(frame))
+    }
+
+    /// Prepends the frame header to the plaintext, compressing it if compression was negotiated,
+    /// it reaches the threshold and compression actually saves space.
+    fn compress(&self, channel: ChannelId, plaintext: &[u8]) -> io::Result<Vec<u8>> {
+        if let Some(threshold) = self.compression_threshold {
+            if plaintext.len() >= threshold {
+                let compressed = zstd::bulk::compress(plaintext, COMPRESSION_LEVEL)?;
+
+                if compressed.len() < plaintext.len() {
+                    let header = header(FLAG_COMPRESSED, channel);
+                    return Ok([header.as_slice(), &compressed].concat());
+                }
+            }
+        }
+        Ok([header(0, channel).as_slice(), plaintext].concat())
+    }
+
+    /// Returns the body of a data frame with the given header flags, decompressing it if
+    /// necessary.
+    ///
+    /// Decompressed frames may not exceed the maximum frame size either.
+    fn decompress(&self, flags: u8, body: &[u8]) -> io::Result<Vec<u8>> {
+        match flags {
+            FLAG_COMPRESSED if self.compression_threshold.is_some() => {
+                zstd::bulk::decompress(body, self.max_frame_size as usize)
+                    .map_err(|_| StreamError::MalformedFrame.into())
+            }
+            0 => Ok(body.to_vec()),
+            _ => Err(StreamError::MalformedFrame.into()),
+        }
+    }
+
+    /// Returns the flow control state of the channel an incoming frame belongs to.
+    fn recv_window(&mut self, channel: ChannelId) -> io::Result<&mut Window> {
+        self.windows
+            .get_mut(channel as usize)
+            .ok_or_else(|| StreamError::UnknownChannel(channel).into())
+    }
+
+    /// Returns true if the current send key exhausted its byte or time budget.
+    fn rekey_due(&self) -> bool {
+        self.sent_since_rekey >= self.rekey_after_bytes
+            || self.last_rekey.elapsed() >= self.rekey_interval
+    }
+
+    /// Encrypts the plaintext into length-prefixed frames on the given channel, ready to be
+    /// written to the transport.
+    ///
+    /// If the current key exhausted its budget, a rekey frame is sealed under the current key
+    /// first and the plaintext is sealed under the next one. The plaintext counts against the
+    /// channel's window, which the caller must check with [can_send](Self::can_send) first.
+    pub(crate) fn seal(&mut self, channel: ChannelId, plaintext: &[u8]) -> io::Result<Vec<u8>> {
+        self.ensure_open()?;
+
+        if channel >= CHANNELS {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("Channel {channel} does not exist"),
+            ));
+        }
+        let mut frames = Vec::new();
+
+        if self.rekey_due() {
+            self.seal_frame(&header(FLAG_REKEY, CONTROL_CHANNEL), &mut frames)?;
+            self.send_cipher = next_cipher(&mut self.send_key);
+            self.sent_since_rekey = 0;
+            self.last_rekey = Instant::now();
+        }
+
+        let payload = self.compress(channel, plaintext)?;
+        self.seal_frame(&payload, &mut frames)?;
+        self.sent_since_rekey += payload.len() as u64;
+        self.windows[channel as usize].unacked += plaintext.len() as u64;
+        Ok(frames)
+    }
+
+    /// Called whenever a read from the transport times out.
+    ///
+    /// Returns [StreamError::PeerTimedOut] if the peer missed too many heartbeats, measured in
+    /// the peer's own heartbeat interval once it is known. Otherwise returns a heartbeat frame to
+    /// be written to the transport if nothing was sent for a heartbeat interval, or if the peer
+    /// was not told the interval yet.
+    pub(crate) fn tick(&mut self) -> io::Result<Option<Vec<u8>>> {
+        self.ensure_open()?;
+
+        if !self.heartbeats {
+            return Ok(None);
+        }
+
+        let peer_interval = self
+            .peer_heartbeat_interval
+            .unwrap_or(self.heartbeat_interval);
+
+        if self.last_received.elapsed() >= peer_interval * self.max_missed_heartbeats {
+            self.closed = true;
+            return Err(StreamError::PeerTimedOut.into());
+        }
+
+        // Until the first heartbeat arrives, the peer measures silence in its own interval, which
+        // may be much shorter than ours.
+        if self.interval_announced
+            && self
+                .last_sent
+                .is_some_and(|sent| sent.elapsed() < self.heartbeat_interval)
+        {
+            return Ok(None);
+        }
+        self.interval_announced = true;
+
+        let interval = self.heartbeat_interval.as_millis().min(u32::MAX as u128) as u32;
+        let header = header(FLAG_HEARTBEAT, CONTROL_CHANNEL);
+        let payload = [header.as_slice(), &interval.to_le_bytes()].concat();
+        let mut frame = Vec::new();
+
+        self.seal_frame(&payload, &mut frame)?;
+        Ok(Some(frame))
+    }
+
+    /// Encrypts the payload and appends it to the given buffer as a length-prefixed frame.
+    ///
+    /// The frame's sequence number is authenticated along with the ciphertext.
+    fn seal_frame(&mut self, payload: &[u8], frames: &mut Vec<u8>) -> io::Result<()> {
+        let seq = self.send_seq.to_le_bytes();
+        let nonce = ChaCha20Poly1305::generate_nonce(&mut OsRng);
+        let ciphertext = self
+            .send_cipher
+            .encrypt(
+                &nonce,
+                Payload {
+                    msg: payload,
+                    aad: &seq,
+                },
+            )
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "Failed to encrypt frame"))?;
+
+        let len = (SEQ_LEN + NONCE_LEN + ciphertext.len()) as u32;
+
+        frames.reserve(LEN_PREFIX_LEN + len as usize);
+        frames.extend_from_slice(&len.to_le_bytes());
+        frames.extend_from_slice(&seq);
+        frames.extend_from_slice(&nonce);
+        frames.extend_from_slice(&ciphertext);
+
+        self.send_seq += 1;
+        self.last_sent = Some(Instant::now());
+        Ok(())
+    }
+
+    /// Authenticates and decrypts a frame read from the transport, without its length prefix.
+    ///
+    /// Frames must arrive with consecutive sequence numbers, so replayed, reordered and dropped
+    /// frames are rejected. Returns the plaintext of data frames along with their channel, or
+    /// None for rekey, heartbeat and credit frames, which carry no plaintext.
+    pub(crate) fn open(&mut self, frame: &[u8]) -> io::Result<Option<(ChannelId, Vec<u8>)>> {
+        if frame.len() < SEQ_LEN + NONCE_LEN + TAG_LEN {
+            return Err(StreamError::MalformedFrame.into());
+        }
+        let (seq, rest) = frame.split_at(SEQ_LEN);
+        let (nonce, ciphertext) = rest.split_at(NONCE_LEN);
+
+        let payload = self
+            .recv_cipher
+            .decrypt(
+                Nonce::from_slice(nonce),
+                Payload {
+                    msg: ciphertext,
+                    aad: seq,
+                },
+            )
+            .map_err(|_| StreamError::TamperedFrame)?;
+
+        let received = u64::from_le_bytes(seq.try_into().expect("sequence number is 8 bytes"));
+
+        if received < self.recv_seq {
+            return Err(StreamError::ReplayedFrame(received).into());
+        }
+        if received > self.recv_seq {
+            return Err(StreamError::OutOfOrderFrame {
+                expected: self.recv_seq,
+                received,
+            }
+            .into());
+        }
+
+        self.recv_seq += 1;
+        self.last_received = Instant::now();
+
+        let [flags, channel_lo, channel_hi, body @ ..] = payload.as_slice() else {
+            return Err(StreamError::MalformedFrame.into());
+        };
+        let channel = ChannelId::from_le_bytes([*channel_lo, *channel_hi]);
+
+        match (*flags, body) {
+            (FLAG_REKEY, []) => {
+                self.recv_cipher = next_cipher(&mut self.recv_key);
+                Ok(None)
+            }
+            (FLAG_HEARTBEAT, interval) => {
+                let interval = interval
+                    .try_into()
+                    .map_err(|_| StreamError::MalformedFrame)?;
+
+                self.peer_heartbeat_interval =
+                    Some(Duration::from_millis(u32::from_le_bytes(interval) as u64));
+                Ok(None)
+            }
+            (FLAG_CREDIT, credit) => {
+                let credit = credit.try_into().map_err(|_| StreamError::MalformedFrame)?;
+                let window = self.recv_window(channel)?;
+
+                window.unacked = window
+                    .unacked
+                    .checked_sub(u64::from_le_bytes(credit))
+                    .ok_or(StreamError::WindowExceeded(channel))?;
+                Ok(None)
+            }
+            (flags, body) => {
+                // A sender that respects the window never has a full window outstanding here,
+                // since credit is only granted for bytes that were already received.
+                if self.recv_window(channel)?.outstanding >= CHANNEL_WINDOW {
+                    return Err(StreamError::WindowExceeded(channel).into());
+                }
+                let plaintext = self.decompress(flags, body)?;
+
+                self.recv_window(channel)?.outstanding += plaintext.len() as u64;
+                Ok(Some((channel, plaintext)))
+            }
+        }
+    }
+}
+
+/// Returns the frame header with the given flags and channel ID.
+fn header(flags: u8, channel: ChannelId) -> [u8; HEADER_LEN] {
+    let [lo, hi] = channel.to_le_bytes();
+    [flags, lo, hi]
+}
+
+/// Replaces the key with the next one derived from it and returns a cipher using the new key.
+fn next_cipher(key: &mut [u8; 32]) -> ChaCha20Poly1305 {
+    let hk = Hkdf::<Sha256>::new(None, key);
+
+    hk.expand(REKEY_LABEL, key)
+        .expect("32 bytes is a valid HKDF-SHA256 output length");
+    ChaCha20Poly1305::new(Key::from_slice(key))
+}
+
+/// The outcome of filling a buffer from the transport.
+pub(crate) enum Fill {
+    /// The buffer was filled.
+    Complete,
+
+    /// The transport reached its end before any byte was read.
+    End,
+
+    /// The transport had no data available before any byte was read.
+    Idle,
+}
+
+/// Returns true if the error is a transport read timing out.
+fn is_timeout(err: &io::Error) -> bool {
+    matches!(
+        err.kind(),
+        io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+    )
+}
+
+/// A [SecureStream] over a [TcpStream].
+pub type SecureTcpStream = SecureStream<TcpStream>;
+
+/// A write-buffered stream encrypted with ChaCha20-Poly1305.
+///
+/// Runs over any [Read] + [Write] transport, such as a TCP stream, a Unix domain socket or an
+/// in-memory pipe. Every flushed buffer is sent as a single frame with a freshly generated nonce
+/// and an authenticated sequence number, which protects against replayed and reordered frames.
+/// Each direction of the stream uses its own key, which is replaced by a key derived from it once
+/// it exceeds a byte or time budget. If negotiated, large frames are compressed with zstd before
+/// they are encrypted.
+///
+/// The stream carries [CHANNELS] logical channels, each with its own flow control: once a
+/// [CHANNEL_WINDOW] of data sent on a channel was not read by the peer, writes to that channel
+/// wait until it is, while the other channels keep flowing. [Write] and [ToraRead] use the
+/// [CONTROL_CHANNEL], [flush_to](Self::flush_to) and [reads_any](Self::reads_any) any channel.
+///
+/// If negotiated, the stream also sends heartbeats and detects dead peers. Both happen whenever a
+/// read from the transport times out, so the transport should have a read timeout well below the
+/// heartbeat interval. A stream that is not read from for a while must call
+/// [keepalive](Self::keepalive) periodically instead.
+pub struct SecureStream<T> {
+    stream: T,
+    codec: FrameCodec,
+    protocol: Protocol,
     buf: Cursor<Vec<u8>>,
+    pending: VecDeque<(ChannelId, Vec<u8>)>,
 }
 
-impl SecureTcpStream {
-    /// Instantiates a new SecureTcpStream.
-    pub fn new(stream: TcpStream, key: String) -> Self {
+impl<T> SecureStream<T>
+where
+    T: Read + Write,
+{
+    /// Instantiates a new SecureStream.
+    ///
+    /// # Parameters
+    ///
+    /// - stream: The underlying transport.
+    /// - send_key: The 256-bit key used to encrypt outgoing frames.
+    /// - recv_key: The 256-bit key used to decrypt incoming frames.
+    /// - protocol: The protocol negotiated during the handshake.
+    pub fn new(stream: T, send_key: [u8; 32], recv_key: [u8; 32], protocol: Protocol) -> Self {
         Self {
             stream,
-            crypt: MagicCrypt256::new::<_, String>(key, None),
+            codec: FrameCodec::new(send_key, recv_key, protocol),
+            protocol,
             buf: Cursor::new(Vec::new()),
+            pending: VecDeque::new(),
+        }
+    }
+
+    /// Returns a reference to the underlying transport.
+    pub const fn get_ref(&self) -> &T {
+        &self.stream
+    }
+
+    /// Returns the protocol negotiated with the peer.
+    pub const fn protocol(&self) -> Protocol {
+        self.protocol
+    }
+
+    /// Returns the maximum size of a received frame in bytes.
+    pub const fn max_frame_size(&self) -> u32 {
+        self.codec.max_frame_size()
+    }
+
+    /// Sets the maximum size of a received frame in bytes.
+    ///
+    /// Defaults to [DEFAULT_MAX_FRAME_SIZE].
+    pub fn set_max_frame_size(&mut self, max_frame_size: u32) {
+        self.codec.set_max_frame_size(max_frame_size);
+    }
+
+    /// Returns the size in bytes from which outgoing frames are compressed.
+    ///
+    /// Returns None if the peer did not negotiate [Features::COMPRESSION].
+    pub const fn compression_threshold(&self) -> Option<usize> {
+        self.codec.compression_threshold()
+    }
+
+    /// Sets the size in bytes from which outgoing frames are compressed.
+    ///
+    /// Defaults to [DEFAULT_COMPRESSION_THRESHOLD]. Has no effect if compression was not
+    /// negotiated.
+    pub fn set_compression_threshold(&mut self, threshold: usize) {
+        self.codec.set_compression_threshold(threshold);
+    }
+
+    /// Returns the amount of bytes sent under one key before the stream rekeys.
+    pub const fn rekey_after_bytes(&self) -> u64 {
+        self.codec.rekey_after_bytes()
+    }
+
+    /// Sets the amount of bytes sent under one key before the stream rekeys.
+    ///
+    /// Defaults to [DEFAULT_REKEY_AFTER_BYTES].
+    pub fn set_rekey_after_bytes(&mut self, bytes: u64) {
+        self.codec.set_rekey_after_bytes(bytes);
+    }
+
+    /// Returns the amount of time a key is used for before the stream rekeys.
+    pub const fn rekey_interval(&self) -> Duration {
+        self.codec.rekey_interval()
+    }
+
+    /// Sets the amount of time a key is used for before the stream rekeys.
+    ///
+    /// Defaults to [DEFAULT_REKEY_INTERVAL]. The budget is checked whenever a frame is sent, so
+    /// an idle stream rekeys with its next frame.
+    pub fn set_rekey_interval(&mut self, interval: Duration) {
+        self.codec.set_rekey_interval(interval);
+    }
+
+    /// Returns the amount of time without outgoing frames after which a heartbeat is sent.
+    ///
+    /// Returns None if the peer did not negotiate [Features::HEARTBEAT].
+    pub fn heartbeat_interval(&self) -> Option<Duration> {
+        self.codec.heartbeat_interval()
+    }
+
+    /// Sets the amount of time without outgoing frames after which a heartbeat is sent.
+    ///
+    /// Defaults to [DEFAULT_HEARTBEAT_INTERVAL]. The interval is announced to the peer, which
+    /// uses it to detect whether this side is dead. Has no effect if heartbeats were not
+    /// negotiated.
+    pub fn set_heartbeat_interval(&mut self, interval: Duration) {
+        self.codec.set_heartbeat_interval(interval);
+    }
+
+    /// Returns the amount of the peer's heartbeat intervals without incoming frames after which
+    /// the peer is considered dead.
+    pub const fn max_missed_heartbeats(&self) -> u32 {
+        self.codec.max_missed_heartbeats()
+    }
+
+    /// Sets the amount of the peer's heartbeat intervals without incoming frames after which the
+    /// peer is considered dead.
+    ///
+    /// Defaults to [DEFAULT_MAX_MISSED_HEARTBEATS].
+    pub fn set_max_missed_heartbeats(&mut self, max_missed: u32) {
+        self.codec.set_max_missed_heartbeats(max_missed);
+    }
+
+    /// Encrypts the write buffer and sends it as a single frame on the given channel.
+    ///
+    /// Waits until the peer read enough of the channel's window, keeping the frames that arrive
+    /// in the meantime for later reads.
+    pub fn flush_to(&mut self, channel: ChannelId) -> io::Result<()> {
+        while !self.codec.can_send(channel) {
+            let Some(frame) = self.read_frame(false)? else {
+                continue;
+            };
+            self.pending.extend(self.codec.open(&frame)?);
+        }
+        let frames = self.codec.seal(channel, self.buf.get_ref())?;
+
+        self.stream.write_all(&frames)?;
+        self.stream.flush()?;
+
+        self.buf.set_position(0);
+        self.buf.get_mut().clear();
+        Ok(())
+    }
+
+    /// Reads the next data frame of any channel and deserializes `F` from it.
+    ///
+    /// Returns the channel the frame was sent on along with the value.
+    pub fn reads_any<F>(&mut self) -> io::Result<(ChannelId, F)>
+    where
+        F: FromReader,
+    {
+        let (channel, payload) = self.read_payload(None)?;
+
+        let mut reader = Cursor::new(payload);
+        Ok((channel, reader.reads()?))
+    }
+
+    /// Reads the next data frame of any channel and deserializes `F` from it, unless the
+    /// transport read times out before a frame arrives.
+    ///
+    /// Returns None if no frame arrived. Sends heartbeats and detects dead peers like
+    /// [keepalive](Self::keepalive).
+    pub fn try_reads_any<F>(&mut self) -> io::Result<Option<(ChannelId, F)>>
+    where
+        F: FromReader,
+    {
+        let Some((channel, payload)) = self.next_payload(None, true)? else {
+            return Ok(None);
+        };
+
+        let mut reader = Cursor::new(payload);
+        Ok(Some((channel, reader.reads()?)))
+    }
+
+    /// Sends a heartbeat if one is due and processes the frames that already arrived.
+    ///
+    /// Returns [StreamError::PeerTimedOut] if the peer missed too many heartbeats. Data frames are
+    /// kept for the next read. Blocks until the transport read times out, so the transport must
+    /// have a read timeout.
+    pub fn keepalive(&mut self) -> io::Result<()> {
+        self.tick()?;
+
+        while let Some(frame) = self.read_frame(true)? {
+            self.pending.extend(self.codec.open(&frame)?);
+        }
+        Ok(())
+    }
+
+    /// Writes a heartbeat if one is due, or fails if the peer is considered dead.
+    fn tick(&mut self) -> io::Result<()> {
+        if let Some(frame) = self.codec.tick()? {
+            self.stream.write_all(&frame)?;
+            self.stream.flush()?;
+        }
+        Ok(())
+    }
+
+    /// Fills the buffer from the transport, ticking whenever a read times out.
+    ///
+    /// If `poll` is true, returns [Fill::Idle] once a read times out before any byte was read.
+    /// Returns [StreamError::Truncated] if the transport reached its end after some bytes were
+    /// read.
+    fn fill(&mut self, buf: &mut [u8], poll: bool) -> io::Result<Fill> {
+        let mut filled = 0;
+
+        while filled < buf.len() {
+            match self.stream.read(&mut buf[filled..]) {
+                Ok(0) if filled == 0 => return Ok(Fill::End),
+                Ok(0) => return Err(StreamError::Truncated.into()),
+                Ok(n) => filled += n,
+                Err(err) if is_timeout(&err) => {
+                    self.tick()?;
+
+                    if poll && filled == 0 {
+                        return Ok(Fill::Idle);
+                    }
+                }
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => {}
+                Err(err) => return Err(err),
+            }
+        }
+        Ok(Fill::Complete)
+    }
+
+    /// Reads a length-prefixed frame from the underlying stream.
+    ///
+    /// The length is checked before the frame is allocated. Returns [io::ErrorKind::UnexpectedEof]
+    /// if the transport was closed between frames, or [StreamError::Truncated] if it was closed
+    /// in the middle of one. If `poll` is true, returns None if no frame is available.
+    fn read_frame(&mut self, poll: bool) -> io::Result<Option<Vec<u8>>> {
+        let mut prefix = [0; LEN_PREFIX_LEN];
+
+        match self.fill(&mut prefix, poll)? {
+            Fill::Complete => {}
+            Fill::End => return Err(io::ErrorKind::UnexpectedEof.into()),
+            Fill::Idle => return Ok(None),
+        }
+        let len = self.codec.check_len(u32::from_le_bytes(prefix))?;
+
+        let mut frame = vec![0; len];
+        match self.fill(&mut frame, false)? {
+            Fill::Complete => Ok(Some(frame)),
+            _ => Err(StreamError::Truncated.into()),
+        }
+    }
+
+    /// Returns the payload of the next data frame on the given channel, or on any channel if
+    /// None, along with its channel.
+    fn read_payload(&mut self, channel: Option<ChannelId>) -> io::Result<(ChannelId, Vec<u8>)> {
+        loop {
+            if let Some(payload) = self.next_payload(channel, false)? {
+                return Ok(payload);
+            }
+        }
+    }
+
+    /// Returns the next payload on the given channel, or on any channel if None, and grants it
+    /// back to the peer.
+    ///
+    /// Payloads of other channels are kept for later reads. If `poll` is true, returns None once
+    /// no frame is available.
+    fn next_payload(
+        &mut self,
+        channel: Option<ChannelId>,
+        poll: bool,
+    ) -> io::Result<Option<(ChannelId, Vec<u8>)>> {
+        let wanted = |id: ChannelId| channel.is_none_or(|channel| channel == id);
+
+        let (id, payload) = match self.pending.iter().position(|(id, _)| wanted(*id)) {
+            Some(index) => self.pending.remove(index).expect("index is in bounds"),
+            None => loop {
+                let Some(frame) = self.read_frame(poll)? else {
+                    return Ok(None);
+                };
+
+                match self.codec.open(&frame)? {
+                    Some((id, payload)) if wanted(id) => break (id, payload),
+                    Some(payload) => self.pending.push_back(payload),
+                    None => {}
+                }
+            },
+        };
+
+        if let Some(frame) = self.codec.consume(id, payload.len())? {
+            self.stream.write_all(&frame)?;
+            self.stream.flush()?;
         }
+        Ok(Some((id, payload)))
     }
 }
 
-impl ToraRead for SecureTcpStream {
-    fn reads<T>(&mut self) -> io::Result<T> where T: FromReader {
-        let data: Vec<u8> = self.stream.reads()?;
-        let bytes = self
-            .crypt
-            .decrypt_bytes_to_bytes(&data)
-            .map_err(|_| io::ErrorKind::InvalidInput)?;
+impl<T> ToraRead for SecureStream<T>
+where
+    T: Read + Write,
+{
+    fn reads<F>(&mut self) -> io::Result<F>
+    where
+        F: FromReader,
+    {
+        let (_, payload) = self.read_payload(Some(CONTROL_CHANNEL))?;
 
-        let mut reader = Cursor::new(bytes);
+        let mut reader = Cursor::new(payload);
         reader.reads()
     }
 }
 
-impl Write for SecureTcpStream {
+impl<T> Write for SecureStream<T>
+where
+    T: Read + Write,
+{
     fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
         self.buf.write(buf)
     }
 
     fn flush(&mut self) -> io::Result<()> {
-        let bytes = self.crypt.encrypt_bytes_to_bytes(self.buf.get_ref());
+        self.flush_to(CONTROL_CHANNEL)
+    }
+}
 
-        self.stream.writes(&bytes)?;
-        self.stream.flush()?;
+#[cfg(test)]
+pub(crate) mod tests {
+    use std::sync::mpsc;
+    use std::sync::mpsc::{Receiver, Sender};
+    use std::thread;
 
-        self.buf.set_position(0);
-        self.buf.get_mut().clear();
+    use tora::write::ToraWrite;
+
+    use super::*;
+    use crate::handshake::PROTOCOL_VERSION;
+
+    /// One end of an in-memory, bidirectional pipe.
+    pub(crate) struct Pipe {
+        tx: Sender<Vec<u8>>,
+        rx: Receiver<Vec<u8>>,
+        buf: VecDeque<u8>,
+    }
+
+    impl Read for Pipe {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            if self.buf.is_empty() {
+                match self.rx.recv() {
+                    Ok(data) => self.buf.extend(data),
+                    Err(_) => return Ok(0),
+                }
+            }
+            self.buf.read(buf)
+        }
+    }
+
+    impl Write for Pipe {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.tx
+                .send(buf.to_vec())
+                .map_err(|_| io::ErrorKind::BrokenPipe)?;
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    /// Returns both ends of an in-memory, bidirectional pipe.
+    pub(crate) fn pipe() -> (Pipe, Pipe) {
+        let (a_tx, b_rx) = mpsc::channel();
+        let (b_tx, a_rx) = mpsc::channel();
+
+        let end = |tx, rx| Pipe {
+            tx,
+            rx,
+            buf: VecDeque::new(),
+        };
+        (end(a_tx, a_rx), end(b_tx, b_rx))
+    }
+
+    /// Returns the protocol with all features enabled.
+    fn protocol() -> Protocol {
+        Protocol::new(PROTOCOL_VERSION, Features::SUPPORTED)
+    }
+
+    /// Returns a sending and a receiving codec with matching keys.
+    fn codecs() -> (FrameCodec, FrameCodec) {
+        (
+            FrameCodec::new([1; 32], [2; 32], protocol()),
+            FrameCodec::new([2; 32], [1; 32], protocol()),
+        )
+    }
+
+    /// Splits sealed frames into the frames without their length prefix.
+    fn split(mut frames: &[u8]) -> Vec<Vec<u8>> {
+        let mut split = Vec::new();
+
+        while let Some((prefix, rest)) = frames.split_first_chunk::<LEN_PREFIX_LEN>() {
+            let (frame, rest) = rest.split_at(u32::from_le_bytes(*prefix) as usize);
+
+            split.push(frame.to_vec());
+            frames = rest;
+        }
+        split
+    }
+
+    /// Opens all sealed frames and returns the data frames among them.
+    fn open_all(codec: &mut FrameCodec, frames: &[u8]) -> io::Result<Vec<(ChannelId, Vec<u8>)>> {
+        let mut payloads = Vec::new();
+
+        for frame in split(frames) {
+            payloads.extend(codec.open(&frame)?);
+        }
+        Ok(payloads)
+    }
+
+    /// Asserts that the result failed with the given stream error.
+    fn assert_stream_error<R>(result: io::Result<R>, expected: StreamError) {
+        let err = result.err().expect("expected a stream error");
+        assert_eq!(StreamError::from_io(&err), Some(&expected));
+    }
+
+    #[test]
+    fn tampered_frames_are_rejected() -> io::Result<()> {
+        let (mut sender, mut receiver) = codecs();
+        let frames = sender.seal(CONTROL_CHANNEL, b"data")?;
+
+        for index in [
+            0,
+            SEQ_LEN,
+            SEQ_LEN + NONCE_LEN,
+            frames.len() - LEN_PREFIX_LEN - 1,
+        ] {
+            let mut frame = split(&frames).remove(0);
+            frame[index] ^= 1;
+
+            assert_stream_error(receiver.open(&frame), StreamError::TamperedFrame);
+        }
+        assert_eq!(
+            open_all(&mut receiver, &frames)?,
+            [(CONTROL_CHANNEL, b"data".to_vec())]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn replayed_frames_are_rejected() -> io::Result<()> {
+        let (mut sender, mut receiver) = codecs();
+        let frame = split(&sender.seal(CONTROL_CHANNEL, b"data")?).remove(0);
+
+        receiver.open(&frame)?;
+        assert_stream_error(receiver.open(&frame), StreamError::ReplayedFrame(0));
+        Ok(())
+    }
+
+    #[test]
+    fn skipped_frames_are_rejected() -> io::Result<()> {
+        let (mut sender, mut receiver) = codecs();
+
+        sender.seal(CONTROL_CHANNEL, b"dropped")?;
+        let frame = split(&sender.seal(CONTROL_CHANNEL, b"data")?).remove(0);
+
+        assert_stream_error(
+            receiver.open(&frame),
+            StreamError::OutOfOrderFrame {
+                expected: 0,
+                received: 1,
+            },
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn truncated_streams_are_rejected() -> io::Result<()> {
+        let (mut sender, _) = codecs();
+        let frames = sender.seal(CONTROL_CHANNEL, b"data")?;
+
+        for (len, kind) in [
+            (0, io::ErrorKind::UnexpectedEof),
+            (LEN_PREFIX_LEN - 1, io::ErrorKind::InvalidData),
+            (frames.len() - 1, io::ErrorKind::InvalidData),
+        ] {
+            let (mut a, b) = pipe();
+            let mut stream = SecureStream::new(b, [2; 32], [1; 32], protocol());
+
+            a.write_all(&frames[..len])?;
+            drop(a);
+
+            let err = stream.reads::<Vec<u8>>().unwrap_err();
+
+            assert_eq!(err.kind(), kind);
+            if kind == io::ErrorKind::InvalidData {
+                assert_eq!(StreamError::from_io(&err), Some(&StreamError::Truncated));
+            }
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn frames_survive_forced_rekeys() -> io::Result<()> {
+        let (mut sender, mut receiver) = codecs();
+
+        sender.set_rekey_after_bytes(0);
+
+        for plaintext in [b"first", b"again", b"third"] {
+            let frames = sender.seal(CONTROL_CHANNEL, plaintext)?;
+
+            assert_eq!(split(&frames).len(), 2);
+            assert_eq!(
+                open_all(&mut receiver, &frames)?,
+                [(CONTROL_CHANNEL, plaintext.to_vec())]
+            );
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn large_frames_are_compressed() -> io::Result<()> {
+        let (mut sender, mut receiver) = codecs();
+        let plaintext = b"compressible ".repeat(DEFAULT_COMPRESSION_THRESHOLD);
+        let frames = sender.seal(CONTROL_CHANNEL, &plaintext)?;
+
+        assert!(frames.len() < plaintext.len() / 10);
+        assert_eq!(
+            open_all(&mut receiver, &frames)?,
+            [(CONTROL_CHANNEL, plaintext)]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn frames_are_not_compressed_unless_negotiated() -> io::Result<()> {
+        let protocol = Protocol::new(PROTOCOL_VERSION, Features::HEARTBEAT);
+        let mut sender = FrameCodec::new([1; 32], [2; 32], protocol);
+        let mut receiver = FrameCodec::new([2; 32], [1; 32], protocol);
+        let plaintext = b"compressible ".repeat(DEFAULT_COMPRESSION_THRESHOLD);
+        let frames = sender.seal(CONTROL_CHANNEL, &plaintext)?;
+
+        assert_eq!(
+            frames.len(),
+            LEN_PREFIX_LEN + FRAME_OVERHEAD + plaintext.len()
+        );
+        assert_eq!(
+            open_all(&mut receiver, &frames)?,
+            [(CONTROL_CHANNEL, plaintext)]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn full_window_waits_for_credit() -> io::Result<()> {
+        let (mut sender, mut receiver) = codecs();
+        let chunk = vec![7; CHANNEL_WINDOW as usize / 8];
+        let mut received = Vec::new();
+
+        while sender.can_send(1) {
+            received.extend(open_all(&mut receiver, &sender.seal(1, &chunk)?)?);
+        }
+        assert_eq!(received.len(), 8);
+        assert!(sender.can_send(CONTROL_CHANNEL));
+
+        let mut credit = Vec::new();
+
+        for (channel, payload) in received {
+            assert_eq!(channel, 1);
+            credit.extend(
+                receiver
+                    .consume(channel, payload.len())?
+                    .unwrap_or_default(),
+            );
+        }
+        assert!(open_all(&mut sender, &credit)?.is_empty());
+        assert!(sender.can_send(1));
+        Ok(())
+    }
+
+    #[test]
+    fn exceeding_the_window_is_rejected() -> io::Result<()> {
+        let (mut sender, mut receiver) = codecs();
+        let chunk = vec![7; CHANNEL_WINDOW as usize / 8];
+
+        for _ in 0..8 {
+            open_all(&mut receiver, &sender.seal(1, &chunk)?)?;
+        }
+        let result = open_all(&mut receiver, &sender.seal(1, &chunk)?);
+
+        assert_stream_error(result, StreamError::WindowExceeded(1));
+        Ok(())
+    }
+
+    #[test]
+    fn unknown_channels_are_rejected() -> io::Result<()> {
+        let (mut sender, mut receiver) = codecs();
+        let mut frame = Vec::new();
+
+        sender.seal_frame(&header(0, CHANNELS), &mut frame)?;
+
+        let result = open_all(&mut receiver, &frame);
+
+        assert_stream_error(result, StreamError::UnknownChannel(CHANNELS));
+        assert_eq!(
+            sender.seal(CHANNELS, b"data").unwrap_err().kind(),
+            io::ErrorKind::InvalidInput
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn reads_keep_frames_of_other_channels() -> io::Result<()> {
+        let (a, b) = pipe();
+        let mut a = SecureStream::new(a, [1; 32], [2; 32], protocol());
+        let mut b = SecureStream::new(b, [2; 32], [1; 32], protocol());
+
+        a.writes(&"transfer".to_string())?;
+        a.flush_to(1)?;
+        a.writes(&"control".to_string())?;
+        a.flush()?;
+
+        assert_eq!(b.reads::<String>()?, "control");
+        assert_eq!(b.reads_any::<String>()?, (1, "transfer".to_string()));
+        Ok(())
+    }
+
+    #[test]
+    fn writes_resume_once_the_peer_reads() -> io::Result<()> {
+        let (a, b) = pipe();
+        let mut a = SecureStream::new(a, [1; 32], [2; 32], protocol());
+        let mut b = SecureStream::new(b, [2; 32], [1; 32], protocol());
+        let chunk = vec![7u8; CHANNEL_WINDOW as usize / 4];
+
+        let writer = thread::spawn(move || {
+            for _ in 0..8 {
+                a.writes(&chunk)?;
+                a.flush_to(1)?;
+            }
+            a.writes(&"done".to_string())?;
+            a.flush().map(|()| a)
+        });
+
+        for _ in 0..8 {
+            let (channel, chunk) = b.reads_any::<Vec<u8>>()?;
+
+            assert_eq!((channel, chunk.len()), (1, CHANNEL_WINDOW as usize / 4));
+        }
+        assert_eq!(b.reads::<String>()?, "done");
+        writer.join().expect("writer panicked")?;
         Ok(())
     }
 }
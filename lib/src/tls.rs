@@ -0,0 +1,209 @@
+use std::io;
+use std::io::{Read, Write};
+use std::net::TcpStream;
+use std::ops::{Deref, DerefMut};
+use std::path::Path;
+use std::sync::Arc;
+
+use rustls::client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier};
+use rustls::crypto::{ring, CryptoProvider};
+use rustls::pki_types::pem::PemObject;
+use rustls::pki_types::{CertificateDer, PrivateKeyDer, ServerName, UnixTime};
+use rustls::{
+    CertificateError, ClientConnection, ConnectionCommon, DigitallySignedStruct, ServerConnection,
+    SideData, SignatureScheme, StreamOwned,
+};
+pub use rustls::{ClientConfig, ServerConfig};
+
+/// The server name sent by clients.
+///
+/// Certificates are pinned rather than verified against a name, so any certificate is accepted
+/// as long as it is the pinned one.
+pub const SERVER_NAME: &str = "dori";
+
+/// A TCP stream that is either plain or wrapped in TLS.
+///
+/// Used so that the same connection type can serve both kinds of transport.
+pub enum MaybeTlsStream<C> {
+    /// A plain TCP stream.
+    Plain(TcpStream),
+
+    /// A TCP stream wrapped in TLS.
+    Tls(Box<StreamOwned<C, TcpStream>>),
+}
+
+impl<C, S> Read for MaybeTlsStream<C>
+where
+    C: DerefMut + Deref<Target = ConnectionCommon<S>>,
+    S: SideData,
+{
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            Self::Plain(stream) => stream.read(buf),
+            Self::Tls(stream) => stream.read(buf),
+        }
+    }
+}
+
+impl<C, S> Write for MaybeTlsStream<C>
+where
+    C: DerefMut + Deref<Target = ConnectionCommon<S>>,
+    S: SideData,
+{
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Self::Plain(stream) => stream.write(buf),
+            Self::Tls(stream) => stream.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Self::Plain(stream) => stream.flush(),
+            Self::Tls(stream) => stream.flush(),
+        }
+    }
+}
+
+/// The transport of a host, optionally wrapped in TLS.
+pub type HostTransport = MaybeTlsStream<ServerConnection>;
+
+/// The transport of a client, optionally wrapped in TLS.
+pub type ClientTransport = MaybeTlsStream<ClientConnection>;
+
+/// Accepts only the pinned certificate, regardless of its issuer, name or validity period.
+#[derive(Debug)]
+struct PinnedCertVerifier {
+    pinned: CertificateDer<'static>,
+    provider: Arc<CryptoProvider>,
+}
+
+impl ServerCertVerifier for PinnedCertVerifier {
+    fn verify_server_cert(
+        &self,
+        end_entity: &CertificateDer<'_>,
+        _intermediates: &[CertificateDer<'_>],
+        _server_name: &ServerName<'_>,
+        _ocsp_response: &[u8],
+        _now: UnixTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        match end_entity.as_ref() == self.pinned.as_ref() {
+            true => Ok(ServerCertVerified::assertion()),
+            false => Err(rustls::Error::InvalidCertificate(
+                CertificateError::ApplicationVerificationFailure,
+            )),
+        }
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        rustls::crypto::verify_tls12_signature(
+            message,
+            cert,
+            dss,
+            &self.provider.signature_verification_algorithms,
+        )
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        rustls::crypto::verify_tls13_signature(
+            message,
+            cert,
+            dss,
+            &self.provider.signature_verification_algorithms,
+        )
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.provider
+            .signature_verification_algorithms
+            .supported_schemes()
+    }
+}
+
+/// Reads the first certificate from the PEM file at the given path.
+pub fn load_certificate<P>(path: P) -> io::Result<CertificateDer<'static>>
+where
+    P: AsRef<Path>,
+{
+    CertificateDer::from_pem_file(path)
+        .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+}
+
+/// Reads the first private key from the PEM file at the given path.
+pub fn load_private_key<P>(path: P) -> io::Result<PrivateKeyDer<'static>>
+where
+    P: AsRef<Path>,
+{
+    PrivateKeyDer::from_pem_file(path)
+        .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+}
+
+/// Creates the TLS configuration of a host presenting the given certificate.
+pub fn server_config(
+    cert: CertificateDer<'static>,
+    key: PrivateKeyDer<'static>,
+) -> io::Result<Arc<ServerConfig>> {
+    let config = ServerConfig::builder_with_provider(Arc::new(ring::default_provider()))
+        .with_safe_default_protocol_versions()
+        .and_then(|builder| {
+            builder
+                .with_no_client_auth()
+                .with_single_cert(vec![cert], key)
+        })
+        .map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err))?;
+
+    Ok(Arc::new(config))
+}
+
+/// Creates the TLS configuration of a client that only trusts the given, pinned certificate.
+pub fn client_config(pinned: CertificateDer<'static>) -> io::Result<Arc<ClientConfig>> {
+    let provider = Arc::new(ring::default_provider());
+    let verifier = PinnedCertVerifier {
+        pinned,
+        provider: provider.clone(),
+    };
+
+    let config = ClientConfig::builder_with_provider(provider)
+        .with_safe_default_protocol_versions()
+        .map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err))?
+        .dangerous()
+        .with_custom_certificate_verifier(Arc::new(verifier))
+        .with_no_client_auth();
+
+    Ok(Arc::new(config))
+}
+
+/// Performs the TLS handshake as the host over the given TCP stream.
+pub fn accept(config: Arc<ServerConfig>, mut stream: TcpStream) -> io::Result<HostTransport> {
+    let mut conn = ServerConnection::new(config).map_err(io::Error::other)?;
+
+    while conn.is_handshaking() {
+        conn.complete_io(&mut stream)?;
+    }
+    let stream = StreamOwned::new(conn, stream);
+    Ok(MaybeTlsStream::Tls(Box::new(stream)))
+}
+
+/// Performs the TLS handshake as the client over the given TCP stream.
+///
+/// Fails with [io::ErrorKind::InvalidData] if the host does not present the pinned certificate.
+pub fn connect(config: Arc<ClientConfig>, mut stream: TcpStream) -> io::Result<ClientTransport> {
+    let name = ServerName::try_from(SERVER_NAME).expect("server name is a valid DNS name");
+    let mut conn = ClientConnection::new(config, name).map_err(io::Error::other)?;
+
+    while conn.is_handshaking() {
+        conn.complete_io(&mut stream)?;
+    }
+    let stream = StreamOwned::new(conn, stream);
+    Ok(MaybeTlsStream::Tls(Box::new(stream)))
+}
@@ -0,0 +1,182 @@
+use std::ffi::OsString;
+#[cfg(unix)]
+use std::fs::Permissions;
+use std::fs::{File, Metadata};
+use std::io::Write;
+#[cfg(unix)]
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+use std::{fs, io};
+
+use sha2::{Digest, Sha256};
+
+use crate::operation::{HashAlgorithm, TreeEntry};
+use crate::stream::ChannelId;
+
+/// The amount of file content carried by a single chunk.
+pub const CHUNK_LEN: usize = 1024 * 1024;
+
+/// The channel file chunks are sent on, so that a transfer does not hold up the operations sent
+/// on the [CONTROL_CHANNEL](crate::stream::CONTROL_CHANNEL) meanwhile.
+pub const TRANSFER_CHANNEL: ChannelId = 1;
+
+/// The length of a file digest in bytes.
+pub const DIGEST_LEN: usize = 32;
+
+/// The extension appended to the path of a file while it is being received.
+pub const PARTIAL_EXTENSION: &str = "dori-part";
+
+/// Computes the SHA-256 digest that transferred files are verified with.
+///
+/// Data is hashed as it is written, so a digest can be computed while a file is streamed.
+#[derive(Clone, Default)]
+pub struct FileHasher(Sha256);
+
+impl FileHasher {
+    /// Instantiates a new FileHasher.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Hashes the given data.
+    pub fn update(&mut self, data: &[u8]) {
+        self.0.update(data);
+    }
+
+    /// Returns the digest of all data hashed so far.
+    pub fn finish(self) -> Vec<u8> {
+        self.0.finalize().to_vec()
+    }
+}
+
+impl Write for FileHasher {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.update(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Returns the SHA-256 digest of the file at the given path.
+pub fn digest_file<P>(path: P) -> io::Result<Vec<u8>>
+where
+    P: AsRef<Path>,
+{
+    hash_file(path, HashAlgorithm::Sha256)
+}
+
+/// Returns the digest of the file at the given path, computed with the given algorithm.
+pub fn hash_file<P>(path: P, algorithm: HashAlgorithm) -> io::Result<Vec<u8>>
+where
+    P: AsRef<Path>,
+{
+    let mut file = File::open(path)?;
+
+    match algorithm {
+        HashAlgorithm::Sha256 => {
+            let mut hasher = FileHasher::new();
+
+            io::copy(&mut file, &mut hasher)?;
+            Ok(hasher.finish())
+        }
+        HashAlgorithm::Blake3 => {
+            let mut hasher = blake3::Hasher::new();
+
+            io::copy(&mut file, &mut hasher)?;
+            Ok(hasher.finalize().as_bytes().to_vec())
+        }
+    }
+}
+
+/// Returns the path a file is received at before it is verified and moved to the given path.
+///
+/// Keeping partially received files around allows interrupted transfers to resume.
+pub fn partial_path<P>(path: P) -> PathBuf
+where
+    P: AsRef<Path>,
+{
+    let mut partial = OsString::from(path.as_ref());
+
+    partial.push(".");
+    partial.push(PARTIAL_EXTENSION);
+    PathBuf::from(partial)
+}
+
+/// Returns the Unix mode of the file.
+#[cfg(unix)]
+pub fn file_mode(metadata: &Metadata) -> Option<u32> {
+    Some(metadata.permissions().mode())
+}
+
+/// Returns None, since modes are only supported on Unix.
+#[cfg(not(unix))]
+pub fn file_mode(_metadata: &Metadata) -> Option<u32> {
+    None
+}
+
+/// Applies the Unix mode to the file at the given path.
+#[cfg(unix)]
+pub fn set_file_mode<P>(path: P, mode: u32) -> io::Result<()>
+where
+    P: AsRef<Path>,
+{
+    fs::set_permissions(path, Permissions::from_mode(mode))
+}
+
+/// Does nothing, since modes are only supported on Unix.
+#[cfg(not(unix))]
+pub fn set_file_mode<P>(_path: P, _mode: u32) -> io::Result<()>
+where
+    P: AsRef<Path>,
+{
+    Ok(())
+}
+
+/// Lists the files and directories below the given directory, every directory before its
+/// contents.
+///
+/// Symbolic links are listed as what they point to, except for links to directories, which are
+/// skipped so that cycles are never walked into. Broken links and special files are skipped too.
+pub fn walk_tree<P>(root: P) -> io::Result<Vec<TreeEntry>>
+where
+    P: AsRef<Path>,
+{
+    let mut entries = Vec::new();
+
+    walk_dir(root.as_ref(), "", &mut entries)?;
+    Ok(entries)
+}
+
+/// Appends the descendants of the directory to the entries, prefixing their paths.
+fn walk_dir(dir: &Path, prefix: &str, entries: &mut Vec<TreeEntry>) -> io::Result<()> {
+    let mut children = fs::read_dir(dir)?.collect::<io::Result<Vec<_>>>()?;
+    children.sort_by_key(|child| child.file_name());
+
+    for child in children {
+        let name = child.file_name().into_string().map_err(|name| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{} is not valid Unicode", name.to_string_lossy()),
+            )
+        })?;
+        let path = format!("{prefix}{name}");
+        let is_link = child.file_type()?.is_symlink();
+
+        let metadata = match fs::metadata(child.path()) {
+            Ok(metadata) => metadata,
+            Err(_) if is_link => continue,
+            Err(err) => return Err(err),
+        };
+
+        if metadata.is_dir() && !is_link {
+            entries.push(TreeEntry::new(path.clone(), true, 0));
+            walk_dir(&child.path(), &format!("{path}/"), entries)?;
+        } else if metadata.is_file() {
+            entries.push(TreeEntry::new(path, false, metadata.len()));
+        }
+    }
+    Ok(())
+}